@@ -0,0 +1,104 @@
+use crate::fs::{FileSystem, Tree};
+
+/// Every directory of `fs` with its cumulative size, in pre-order
+pub fn dir_sizes<F: FileSystem>(fs: &F) -> Vec<(F::Dir, usize)> {
+    let mut sizes = vec![];
+    let mut pending = vec![fs.root()];
+    while let Some(dir) = pending.pop() {
+        sizes.push((dir, dir.size(fs)));
+        pending.extend(fs.subdirs(dir).into_iter().rev());
+    }
+    sizes
+}
+
+/// Total size of the directories of at most `limit` bytes, a directory being
+/// counted again in every small directory that holds it (part 1)
+pub fn total_of_small_dirs<F: FileSystem>(fs: &F, limit: usize) -> usize {
+    dir_sizes(fs).into_iter().map(|(_, size)| size).filter(|size| *size <= limit).sum()
+}
+
+/// Smallest directory whose removal leaves at least `required` bytes free on
+/// a disk of `capacity` bytes, with its size (part 2). `None` if enough space
+/// is free already, or if no directory is large enough.
+pub fn smallest_dir_to_free<F: FileSystem>(fs: &F, capacity: usize, required: usize) -> Option<(F::Dir, usize)> {
+    let used = fs.root().size(fs);
+    // `used + required - capacity`, which no directory reaches if it overflows
+    let missing = match used.checked_sub(capacity) {
+        Some(excess) => excess.checked_add(required)?,
+        None => required.saturating_sub(capacity - used),
+    };
+    if missing == 0 {
+        return None;
+    }
+    dir_sizes(fs).into_iter().filter(|(_, size)| *size >= missing).min_by_key(|(_, size)| *size)
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::cleanup::Planner;
+    use crate::fixtures::example_state;
+    use crate::frozen::FrozenFileSystem;
+    use crate::fs::Path;
+
+    /// Both answers of the puzzle, on any backend
+    fn answers<F: FileSystem>(fs: &F) -> (usize, Option<(String, usize)>) {
+        let part2 = smallest_dir_to_free(fs, 70_000_000, 30_000_000).map(|(dir, size)| (dir.abspath(fs), size));
+        (total_of_small_dirs(fs, 100_000), part2)
+    }
+
+    #[test]
+    fn analysis_backends() {
+        let state = example_state();
+        let frozen = FrozenFileSystem::freeze(&state.fs);
+        let expected = (95437, Some(("/d/".to_string(), 24933642)));
+        assert_eq!(answers(&state.fs), expected);
+        assert_eq!(answers(&frozen), expected);
+
+        let basic: Vec<(String, usize)> =
+            dir_sizes(&state.fs).into_iter().map(|(dir, size)| (dir.abspath(&state.fs), size)).collect();
+        let frozen_sizes: Vec<(String, usize)> =
+            dir_sizes(&frozen).into_iter().map(|(dir, size)| (dir.abspath(&frozen), size)).collect();
+        assert_eq!(basic, frozen_sizes);
+        let plan = Planner::new(&state.fs, 70_000_000, 30_000_000).plan().unwrap();
+        assert_eq!(plan.freed, 24933642);
+
+        assert_eq!(smallest_dir_to_free(&frozen, 70_000_000, 20_000_000), None);
+        assert_eq!(smallest_dir_to_free(&frozen, 10_000_000, 30_000_000), None);
+    }
+
+    /// Builds the same small tree on any backend that accepts mutations
+    fn build<F: FileSystem>(fs: &mut F) -> Result<(), crate::error::Error> {
+        let a = fs.create_directory(fs.root(), "a".into())?;
+        let b = fs.create_directory(a, "b".into())?;
+        fs.create_file(a, "x".into(), 70_000)?;
+        fs.create_file(b, "y".into(), 20_000)?;
+        fs.create_file(fs.root(), "z".into(), 50_000)?;
+        Ok(())
+    }
+
+    #[test]
+    fn analysis_generic_build() {
+        let mut fs = crate::fs::BasicFileSystem::new();
+        build(&mut fs).unwrap();
+        assert_eq!(total_of_small_dirs(&fs, 100_000), 90_000 + 20_000);
+        let mut frozen = FrozenFileSystem::freeze(&fs);
+        assert_eq!(total_of_small_dirs(&frozen, 100_000), 110_000);
+        assert_eq!(build(&mut frozen), Err(crate::error::Error::ReadOnly));
+        assert_eq!(smallest_dir_to_free(&frozen, 10_000_000, 30_000_000), None);
+    }
+
+    /// Sizes near `usize::MAX` do not overflow
+    #[test]
+    fn analysis_large_sizes() {
+        let mut fs = crate::fs::BasicFileSystem::new();
+        let mut a = fs.root().new_directory("a".into(), &mut fs).unwrap();
+        a.new_file("f".into(), usize::MAX - 5, &mut fs).unwrap();
+        let size = |capacity, required| smallest_dir_to_free(&fs, capacity, required).map(|(_, size)| size);
+        assert_eq!(size(usize::MAX, 10), Some(usize::MAX - 5));
+        assert_eq!(size(usize::MAX, 5), None);
+        assert_eq!(size(usize::MAX - 10, 4), Some(usize::MAX - 5));
+        assert_eq!(size(0, 10), None);
+    }
+}
@@ -0,0 +1,425 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::fs::{BasicFileSystem, DirectoryHandle, Entry, Path, Tree};
+use crate::query::glob_match;
+
+/// What a [Planner] minimizes
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Objective {
+    /// Delete the smallest single directory that frees enough space
+    #[default]
+    SingleDirectory,
+    /// Delete any number of directories, none inside another, freeing as few
+    /// bytes as possible, then with as few deletions as possible
+    MinimalBytes,
+    /// Delete as few directories as possible, none inside another, then
+    /// freeing as few bytes as possible
+    FewestDeletions,
+}
+
+/// Directories to delete to free the required space
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Plan {
+    /// In pre-order, empty when there already is enough free space
+    pub dirs: Vec<DirectoryHandle>,
+    /// Bytes of the inodes whose every name is below `dirs`
+    pub freed: usize,
+    /// Used size once `dirs` are deleted
+    pub used: usize,
+}
+
+/// Chooses directories to delete so that a disk of `capacity` bytes holding
+/// the filesystem has at least `required` bytes free, as in day 7 part 2
+pub struct Planner<'a> {
+    fs: &'a BasicFileSystem,
+    capacity: usize,
+    required: usize,
+    objective: Objective,
+    protected: Vec<String>,
+}
+
+/// Inode with names both below a [Candidate] and elsewhere, whose bytes are
+/// only freed once all of its `links` names are deleted
+struct Shared {
+    inode: usize,
+    size: usize,
+    /// Names below the candidate
+    names: usize,
+    links: usize,
+}
+
+/// Directory that may be deleted, `end` is the position in the candidate
+/// list right after its subtree
+struct Candidate {
+    dir: DirectoryHandle,
+    /// Most bytes that deleting the directory frees, with other deletions
+    size: usize,
+    /// Bytes that deleting the directory frees on its own
+    freed: usize,
+    shared: Vec<Shared>,
+    end: usize,
+}
+
+/// Bytes freed by deleting the `chosen` candidates, the inodes they share
+/// with each other included
+fn freed(candidates: &[Candidate], chosen: &[usize]) -> usize {
+    let mut deleted: HashMap<usize, usize> = HashMap::new();
+    let mut freed = 0;
+    for candidate in chosen.iter().map(|position| &candidates[*position]) {
+        freed += candidate.freed;
+        for shared in &candidate.shared {
+            let names = deleted.entry(shared.inode).or_default();
+            *names += shared.names;
+            if *names == shared.links {
+                freed += shared.size;
+            }
+        }
+    }
+    freed
+}
+
+/// Branch and bound over the candidates in pre-order: each one is either
+/// deleted, skipping its subtree, or kept
+struct Search<'a> {
+    candidates: &'a [Candidate],
+    objective: Objective,
+    needed: usize,
+    /// Most bytes that can be freed from each candidate onwards
+    reachable: Vec<usize>,
+    chosen: Vec<usize>,
+    /// Names of the shared inodes below the chosen candidates
+    deleted: HashMap<usize, usize>,
+    best: Option<((usize, usize), Vec<usize>)>,
+}
+
+impl Search<'_> {
+    fn key(&self, freed: usize, count: usize) -> (usize, usize) {
+        match self.objective {
+            Objective::FewestDeletions => (count, freed),
+            _ => (freed, count),
+        }
+    }
+
+    fn run(&mut self, position: usize, freed: usize) {
+        let count = self.chosen.len();
+        if freed >= self.needed {
+            let key = self.key(freed, count);
+            if self.best.as_ref().is_none_or(|(best, _)| key < *best) {
+                self.best = Some((key, self.chosen.clone()));
+            }
+            return;
+        }
+        if position == self.candidates.len() || freed + self.reachable[position] < self.needed {
+            return;
+        }
+        // any completion deletes at least one more directory and reaches `needed`
+        let bound = self.key(self.needed.max(freed + 1), count + 1);
+        if self.best.as_ref().is_some_and(|(best, _)| bound >= *best) {
+            return;
+        }
+        let candidates = self.candidates;
+        let candidate = &candidates[position];
+        // the bytes of a shared inode are freed by the deletion of its last name
+        let mut gain = candidate.freed;
+        for shared in &candidate.shared {
+            let names = self.deleted.entry(shared.inode).or_default();
+            *names += shared.names;
+            if *names == shared.links {
+                gain += shared.size;
+            }
+        }
+        self.chosen.push(position);
+        self.run(candidate.end, freed + gain);
+        self.chosen.pop();
+        for shared in &candidate.shared {
+            *self.deleted.get_mut(&shared.inode).unwrap() -= shared.names;
+        }
+        self.run(position + 1, freed);
+    }
+}
+
+impl<'a> Planner<'a> {
+    pub fn new(fs: &'a BasicFileSystem, capacity: usize, required: usize) -> Self {
+        Planner {
+            fs,
+            capacity,
+            required,
+            objective: Objective::default(),
+            protected: vec![],
+        }
+    }
+
+    pub fn objective(mut self, objective: Objective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Never delete entries whose absolute path matches `pattern`, nor the
+    /// directories containing them. Patterns use `*` and `?` as in
+    /// [crate::query::Query::name] and directory paths end with `/`, so
+    /// `/a/` only protects the directory itself while `/a/*` protects its
+    /// contents as well.
+    pub fn protect(mut self, pattern: &str) -> Self {
+        self.protected.push(pattern.into());
+        self
+    }
+
+    fn is_protected(&self, path: &str) -> bool {
+        self.protected.iter().any(|pattern| glob_match(pattern, path))
+    }
+
+    /// Directories other than the root that hold no protected entry, in
+    /// pre-order. Empty directories are left out since deleting them frees
+    /// nothing.
+    fn candidates(&self) -> Vec<Candidate> {
+        let fs = self.fs;
+        // size and number of names of the inodes with several names, and how
+        // many of those names each directory holds
+        let mut inodes: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut names: HashMap<DirectoryHandle, HashMap<usize, usize>> = HashMap::new();
+        for (_, entry) in fs.root().pre_order(fs) {
+            let Entry::File(file) = entry else { continue };
+            let links = file.handle.links(fs).len();
+            if links < 2 {
+                continue;
+            }
+            inodes.insert(file.inode, (file.size, links));
+            let mut dir = file.dir;
+            loop {
+                *names.entry(dir).or_default().entry(file.inode).or_default() += 1;
+                if dir == fs.root() {
+                    break;
+                }
+                dir = dir.parent(fs);
+            }
+        }
+
+        let mut protected = HashSet::new();
+        for (_, entry) in fs.root().post_order(fs) {
+            let (path, parent) = match entry {
+                Entry::File(file) => (file.handle.abspath(fs), file.dir),
+                Entry::Directory(dir) => (dir.handle.abspath(fs), dir.parent),
+            };
+            let holds_protected = matches!(entry, Entry::Directory(dir) if protected.contains(&dir.handle));
+            if holds_protected || self.is_protected(&path) {
+                protected.insert(parent);
+                if let Entry::Directory(dir) = entry {
+                    protected.insert(dir.handle);
+                }
+            }
+        }
+
+        let mut candidates: Vec<Candidate> = vec![];
+        // positions of the candidates whose subtree is still being visited
+        let mut open: Vec<(usize, usize)> = vec![];
+        for (depth, entry) in fs.root().pre_order(fs) {
+            let Entry::Directory(dir) = entry else { continue };
+            while open.last().is_some_and(|(open_depth, _)| *open_depth >= depth) {
+                let (_, position) = open.pop().unwrap();
+                candidates[position].end = candidates.len();
+            }
+            if dir.is_root() || dir.size == 0 || protected.contains(&dir.handle) {
+                continue;
+            }
+            let shared: Vec<Shared> = names
+                .get(&dir.handle)
+                .into_iter()
+                .flatten()
+                .map(|(inode, names)| {
+                    let (size, links) = inodes[inode];
+                    Shared { inode: *inode, size, names: *names, links }
+                })
+                .filter(|shared| shared.names < shared.links)
+                .collect();
+            // the cached size counts every inode once
+            let freed = dir.size - shared.iter().map(|shared| shared.size).sum::<usize>();
+            open.push((depth, candidates.len()));
+            candidates.push(Candidate { dir: dir.handle, size: dir.size, freed, shared, end: 0 });
+        }
+        for (_, position) in open {
+            candidates[position].end = candidates.len();
+        }
+        candidates
+    }
+
+    /// Cheapest plan for the objective, `None` if no allowed deletion frees
+    /// enough space
+    pub fn plan(&self) -> Option<Plan> {
+        let used = self.fs.root().size(self.fs);
+        let free = self.capacity.saturating_sub(used);
+        if free >= self.required {
+            return Some(Plan { dirs: vec![], freed: 0, used });
+        }
+        let needed = self.required - free;
+        let candidates = self.candidates();
+
+        let chosen = if self.objective == Objective::SingleDirectory {
+            let smallest = (0..candidates.len())
+                .filter(|position| candidates[*position].freed >= needed)
+                .min_by_key(|position| candidates[*position].freed)?;
+            vec![smallest]
+        } else {
+            let mut reachable = vec![0; candidates.len() + 1];
+            for position in (0..candidates.len()).rev() {
+                let candidate = &candidates[position];
+                reachable[position] = reachable[position + 1].max(candidate.size + reachable[candidate.end]);
+            }
+            let mut search = Search {
+                candidates: &candidates,
+                objective: self.objective,
+                needed,
+                reachable,
+                chosen: vec![],
+                deleted: HashMap::new(),
+                best: None,
+            };
+            search.run(0, 0);
+            search.best?.1
+        };
+
+        let dirs: Vec<DirectoryHandle> = chosen.iter().map(|position| candidates[*position].dir).collect();
+        let freed = freed(&candidates, &chosen);
+        Some(Plan { dirs, freed, used: used - freed })
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+
+    /// Builds `/x<i>/` directories each holding a file of the given size, and
+    /// one `/n/` directory nesting `/n/m/`
+    fn sized_fs(sizes: &[usize], nested: (usize, usize)) -> BasicFileSystem {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        for (index, size) in sizes.iter().enumerate() {
+            let mut dir = root.new_directory(format!("x{}", index), &mut fs).unwrap();
+            dir.new_file("f".into(), *size, &mut fs).unwrap();
+        }
+        let mut n = root.new_directory("n".into(), &mut fs).unwrap();
+        n.new_file("f".into(), nested.0, &mut fs).unwrap();
+        let mut m = n.new_directory("m".into(), &mut fs).unwrap();
+        m.new_file("f".into(), nested.1, &mut fs).unwrap();
+        fs
+    }
+
+    fn paths(plan: &Plan, fs: &BasicFileSystem) -> Vec<String> {
+        plan.dirs.iter().map(|dir| dir.abspath(fs)).collect()
+    }
+
+    #[test]
+    fn plan_example() {
+        let state = example_state();
+        let fs = &state.fs;
+        let plan = Planner::new(fs, 70_000_000, 30_000_000).plan().unwrap();
+        assert_eq!(paths(&plan, fs), ["/d/"]);
+        assert_eq!((plan.freed, plan.used), (24933642, 48381165 - 24933642));
+
+        // enough space already
+        let plan = Planner::new(fs, 100_000_000, 30_000_000).plan().unwrap();
+        assert_eq!(plan, Plan { dirs: vec![], freed: 0, used: 48381165 });
+
+        // `/d/` is the only directory large enough, and it is protected
+        assert_eq!(Planner::new(fs, 70_000_000, 30_000_000).protect("/d/k").plan(), None);
+        let plan = Planner::new(fs, 70_000_000, 30_000_000).protect("/a/*").plan().unwrap();
+        assert_eq!(paths(&plan, fs), ["/d/"]);
+    }
+
+    #[test]
+    fn plan_objectives() {
+        // 101 bytes used out of 101
+        let fs = sized_fs(&[10, 10, 6, 20, 30], (15, 10));
+        let planner = |required, objective| Planner::new(&fs, 101, required).objective(objective);
+
+        let plan = planner(25, Objective::SingleDirectory).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed, plan.used), (vec!["/n/".into()], 25, 76));
+        let plan = planner(25, Objective::MinimalBytes).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed), (vec!["/n/".into()], 25));
+
+        let plan = planner(26, Objective::MinimalBytes).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed), (vec!["/x2/".into(), "/x3/".into()], 26));
+        let plan = planner(26, Objective::FewestDeletions).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed), (vec!["/x4/".into()], 30));
+
+        // nested directories are never deleted together
+        let plan = planner(101, Objective::MinimalBytes).plan().unwrap();
+        assert_eq!((plan.dirs.len(), plan.freed, plan.used), (6, 101, 0));
+        assert_eq!(planner(102, Objective::MinimalBytes).plan(), None);
+
+        let plan = planner(25, Objective::MinimalBytes)
+            .protect("/n/")
+            .protect("/x1/")
+            .protect("/x3/*")
+            .plan()
+            .unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed), (vec!["/x0/".into(), "/x2/".into(), "/n/m/".into()], 26));
+    }
+
+    /// Hard-linked bytes are only freed once every name is deleted
+    #[test]
+    fn plan_hard_links() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut x = root.new_directory("x".into(), &mut fs).unwrap();
+        let f = x.new_file("f".into(), 100, &mut fs).unwrap();
+        let mut y = root.new_directory("y".into(), &mut fs).unwrap();
+        y.new_hard_link("g".into(), f, &mut fs).unwrap();
+
+        // 100 bytes used out of 150, neither directory frees anything alone
+        assert_eq!(Planner::new(&fs, 150, 100).plan(), None);
+        let plan = Planner::new(&fs, 150, 100).objective(Objective::MinimalBytes).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed, plan.used), (vec!["/x/".into(), "/y/".into()], 100, 0));
+
+        // 160 bytes used out of 210
+        let mut z = root.new_directory("z".into(), &mut fs).unwrap();
+        z.new_file("h".into(), 60, &mut fs).unwrap();
+        let plan = Planner::new(&fs, 210, 100).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed, plan.used), (vec!["/z/".into()], 60, 100));
+        assert_eq!(Planner::new(&fs, 210, 150).plan(), None);
+        let plan = Planner::new(&fs, 210, 150).objective(Objective::FewestDeletions).plan().unwrap();
+        assert_eq!((paths(&plan, &fs), plan.freed, plan.used), (vec!["/x/".into(), "/y/".into()], 100, 60));
+        let plan = Planner::new(&fs, 210, 210).objective(Objective::MinimalBytes).plan().unwrap();
+        assert_eq!((plan.dirs.len(), plan.freed, plan.used), (3, 160, 0));
+    }
+
+    /// The search agrees with trying every set of non-nested directories
+    #[test]
+    fn plan_exhaustive() {
+        let sizes = [3, 7, 1, 12, 9, 4, 6];
+        let fs = sized_fs(&sizes, (5, 8));
+        let dirs: Vec<(String, usize)> = fs
+            .dir_sizes()
+            .into_iter()
+            .filter(|(dir, _)| *dir != fs.root())
+            .map(|(dir, size)| (dir.abspath(&fs), size))
+            .collect();
+        let used = fs.root().size(&fs);
+        for required in 1..=used {
+            let mut best_bytes: Option<(usize, usize)> = None;
+            let mut best_count: Option<(usize, usize)> = None;
+            for mask in 0u32..(1 << dirs.len()) {
+                let chosen: Vec<&(String, usize)> =
+                    (0..dirs.len()).filter(|i| mask & (1 << i) != 0).map(|i| &dirs[i]).collect();
+                let nested = chosen
+                    .iter()
+                    .any(|(a, _)| chosen.iter().any(|(b, _)| a != b && b.starts_with(a.as_str())));
+                let freed: usize = chosen.iter().map(|(_, size)| size).sum();
+                if nested || freed < required {
+                    continue;
+                }
+                if best_bytes.is_none_or(|best| (freed, chosen.len()) < best) {
+                    best_bytes = Some((freed, chosen.len()));
+                }
+                if best_count.is_none_or(|best| (chosen.len(), freed) < best) {
+                    best_count = Some((chosen.len(), freed));
+                }
+            }
+            let plan = Planner::new(&fs, used, required).objective(Objective::MinimalBytes).plan();
+            assert_eq!(plan.map(|plan| (plan.freed, plan.dirs.len())), best_bytes);
+            let plan = Planner::new(&fs, used, required).objective(Objective::FewestDeletions).plan();
+            assert_eq!(plan.map(|plan| (plan.dirs.len(), plan.freed)), best_count);
+        }
+    }
+}
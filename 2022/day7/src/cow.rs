@@ -0,0 +1,221 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Number of elements per chunk of a [CowVec]
+pub const CHUNK: usize = 64;
+
+/// Vector whose clones share their elements until they are written to.
+///
+/// Elements are stored in chunks of [CHUNK] behind reference counts, so a
+/// clone only increments a counter. The first write to a chunk that is
+/// shared copies that chunk and the list of chunks, the other chunks stay
+/// shared. Reading never copies.
+pub struct CowVec<T> {
+    chunks: Arc<Vec<Arc<Vec<T>>>>,
+    len: usize,
+}
+
+impl<T> CowVec<T> {
+    pub fn new() -> Self {
+        CowVec { chunks: Arc::new(vec![]), len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.chunks.get(index / CHUNK)?.get(index % CHUNK)
+    }
+
+    pub fn first(&self) -> Option<&T> {
+        self.get(0)
+    }
+
+    pub fn last(&self) -> Option<&T> {
+        self.get(self.len.checked_sub(1)?)
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    /// Number of chunks stored only once between `self` and `other`, i.e.
+    /// not copied since one was cloned from the other
+    pub fn shared_chunks(&self, other: &Self) -> usize {
+        if Arc::ptr_eq(&self.chunks, &other.chunks) {
+            return self.chunks.len();
+        }
+        self.chunks.iter().zip(other.chunks.iter()).filter(|(a, b)| Arc::ptr_eq(a, b)).count()
+    }
+}
+
+impl<T: Clone> CowVec<T> {
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len {
+            return None;
+        }
+        let chunk = &mut Arc::make_mut(&mut self.chunks)[index / CHUNK];
+        Some(&mut Arc::make_mut(chunk)[index % CHUNK])
+    }
+
+    pub fn first_mut(&mut self) -> Option<&mut T> {
+        self.get_mut(0)
+    }
+
+    pub fn push(&mut self, value: T) {
+        let chunks = Arc::make_mut(&mut self.chunks);
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() < CHUNK => Arc::make_mut(chunk).push(value),
+            _ => {
+                let mut chunk = Vec::with_capacity(CHUNK);
+                chunk.push(value);
+                chunks.push(Arc::new(chunk));
+            },
+        }
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let chunks = Arc::make_mut(&mut self.chunks);
+        let value = Arc::make_mut(chunks.last_mut().unwrap()).pop();
+        if chunks.last().is_some_and(|chunk| chunk.is_empty()) {
+            chunks.pop();
+        }
+        self.len -= 1;
+        value
+    }
+
+    /// Copies every chunk that is shared
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = &mut T> {
+        Arc::make_mut(&mut self.chunks).iter_mut().flat_map(|chunk| Arc::make_mut(chunk).iter_mut())
+    }
+}
+
+impl<T> Clone for CowVec<T> {
+    fn clone(&self) -> Self {
+        CowVec { chunks: Arc::clone(&self.chunks), len: self.len }
+    }
+}
+
+impl<T> Default for CowVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for CowVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index)
+            .unwrap_or_else(|| panic!("index out of bounds: the len is {} but the index is {}", self.len, index))
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for CowVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        let len = self.len;
+        self.get_mut(index)
+            .unwrap_or_else(|| panic!("index out of bounds: the len is {} but the index is {}", len, index))
+    }
+}
+
+impl<T: Clone> FromIterator<T> for CowVec<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut vec = CowVec::new();
+        iter.into_iter().for_each(|value| vec.push(value));
+        vec
+    }
+}
+
+impl<T: Clone> From<Vec<T>> for CowVec<T> {
+    fn from(values: Vec<T>) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<T: Clone, const N: usize> From<[T; N]> for CowVec<T> {
+    fn from(values: [T; N]) -> Self {
+        values.into_iter().collect()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a CowVec<T> {
+    type Item = &'a T;
+    type IntoIter = Box<dyn DoubleEndedIterator<Item = &'a T> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for CowVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for CowVec<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for CowVec<T> {}
+
+impl<T: PartialEq, const N: usize> PartialEq<[T; N]> for CowVec<T> {
+    fn eq(&self, other: &[T; N]) -> bool {
+        self.len == N && self.iter().eq(other.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for CowVec<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+        // the length is unknown to `collect_seq` behind the chunks
+        let mut seq = serializer.serialize_seq(Some(self.len))?;
+        for value in self.iter() {
+            seq.serialize_element(value)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+
+    #[test]
+    fn cow_vec_sharing() {
+        let mut a: CowVec<usize> = (0..200).collect();
+        assert_eq!((a.len(), a[199], a.first(), a.last()), (200, 199, Some(&0), Some(&199)));
+        let mut b = a.clone();
+        assert_eq!(a.shared_chunks(&b), 4);
+
+        b[70] = 1000;
+        assert_eq!((a[70], b[70], a.shared_chunks(&b)), (70, 1000, 3));
+        b.push(200);
+        assert_eq!((a.len(), b.len(), a.shared_chunks(&b)), (200, 201, 2));
+        assert_eq!(b.pop(), Some(200));
+        assert_eq!(b.pop(), Some(199));
+        a.iter_mut().for_each(|value| *value += 1);
+        assert_eq!((a[0], b[0], a.shared_chunks(&b)), (1, 0, 0));
+
+        let mut c: CowVec<usize> = vec![1, 2].into();
+        assert_eq!(c, [1, 2]);
+        assert_eq!((c.pop(), c.pop(), c.pop(), c.is_empty()), (Some(2), Some(1), None, true));
+        assert_eq!(c.get_mut(0), None);
+        assert_eq!(format!("{:?}", b.iter().rev().take(2).collect::<Vec<_>>()), "[198, 197]");
+    }
+}
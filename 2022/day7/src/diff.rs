@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::fs::{BasicFileSystem, Entry, Path};
+use crate::query::Kind;
+
+/// How an entry differs between two filesystems
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Change {
+    Added,
+    Removed,
+    Resized,
+}
+
+/// An entry whose path only exists on one side, or whose size differs.
+/// Directory sizes are cumulative, so every change below a directory is also
+/// reflected in the delta of the directory itself.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct EntryDiff {
+    /// Absolute path, directories end with the separator
+    pub path: String,
+    pub kind: Kind,
+    pub change: Change,
+    pub before: Option<usize>,
+    pub after: Option<usize>,
+}
+
+impl EntryDiff {
+    /// Size change, as an `i128` since sizes use the whole range of `usize`
+    pub fn delta(&self) -> i128 {
+        self.after.unwrap_or(0) as i128 - self.before.unwrap_or(0) as i128
+    }
+}
+
+/// Differences between two filesystems, entries being matched by absolute
+/// path. Displays as a listing with one `+`, `-` or `~` line per entry.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Diff {
+    /// Ordered by path, so that directories come before their contents
+    pub entries: Vec<EntryDiff>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, path: &str) -> Option<&EntryDiff> {
+        self.entries
+            .binary_search_by(|entry| entry.path.as_str().cmp(path))
+            .ok()
+            .map(|index| &self.entries[index])
+    }
+
+    /// Size change at `path`, 0 if the entry did not change
+    pub fn delta(&self, path: &str) -> i128 {
+        self.get(path).map_or(0, EntryDiff::delta)
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            let kind = match entry.kind {
+                Kind::File => "file",
+                Kind::Directory => "dir",
+            };
+            match (entry.before, entry.after) {
+                (Some(before), Some(after)) => writeln!(
+                    f,
+                    "~ {} ({}, size={} -> {}, {:+})",
+                    entry.path,
+                    kind,
+                    before,
+                    after,
+                    entry.delta()
+                )?,
+                (None, Some(after)) => writeln!(f, "+ {} ({}, size={})", entry.path, kind, after)?,
+                (Some(before), None) => writeln!(f, "- {} ({}, size={})", entry.path, kind, before)?,
+                (None, None) => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn entries(fs: &BasicFileSystem) -> BTreeMap<String, (Kind, usize)> {
+    fs.root()
+        .pre_order(fs)
+        .map(|(_, entry)| match entry {
+            Entry::File(file) => (file.handle.abspath(fs), (Kind::File, file.size)),
+            Entry::Directory(dir) => (dir.handle.abspath(fs), (Kind::Directory, dir.size)),
+        })
+        .collect()
+}
+
+/// What changed from `before` to `after`
+pub fn diff(before: &BasicFileSystem, after: &BasicFileSystem) -> Diff {
+    let (mut before, after) = (entries(before), entries(after));
+    let mut changes: BTreeMap<String, EntryDiff> = BTreeMap::new();
+    for (path, (kind, size)) in after {
+        let change = match before.remove(&path) {
+            None => EntryDiff {
+                path: path.clone(),
+                kind,
+                change: Change::Added,
+                before: None,
+                after: Some(size),
+            },
+            Some((_, old)) if old != size => EntryDiff {
+                path: path.clone(),
+                kind,
+                change: Change::Resized,
+                before: Some(old),
+                after: Some(size),
+            },
+            Some(_) => continue,
+        };
+        changes.insert(path, change);
+    }
+    for (path, (kind, size)) in before {
+        let change = EntryDiff {
+            path: path.clone(),
+            kind,
+            change: Change::Removed,
+            before: Some(size),
+            after: None,
+        };
+        changes.insert(path, change);
+    }
+    Diff { entries: changes.into_values().collect() }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::{example_state, EXAMPLE};
+    use crate::fs::ConflictPolicy;
+    use crate::parse::parse_with_policy;
+
+    #[test]
+    fn diff_example() {
+        let before = example_state();
+        let after = parse_with_policy(format!("{}$ cd /a/e\n$ rm i\n$ touch 16 j\n$ cd /\n$ rm c.dat\n$ ls\ndir x\n\
+            8504156 b.txt\n$ cd x\n$ ls\n10 y\n", EXAMPLE), ConflictPolicy::KeepLast).unwrap();
+        let diff = diff(&before.fs, &after.fs);
+
+        assert_eq!(diff.to_string(),
+r#"~ / (dir, size=48381165 -> 33532093, -14849072)
+~ /a/ (dir, size=94853 -> 94285, -568)
+~ /a/e/ (dir, size=584 -> 16, -568)
+- /a/e/i (file, size=584)
++ /a/e/j (file, size=16)
+~ /b.txt (file, size=14848514 -> 8504156, -6344358)
+- /c.dat (file, size=8504156)
++ /x/ (dir, size=10)
++ /x/y (file, size=10)
+"#);
+        assert_eq!(diff.get("/a/e/i").map(|entry| entry.change), Some(Change::Removed));
+        assert_eq!(diff.delta("/a/"), -568);
+        assert_eq!(diff.delta("/d/"), 0);
+        let files = diff.entries.iter().filter(|entry| entry.kind == Kind::File);
+        assert_eq!(diff.delta("/"), files.map(EntryDiff::delta).sum::<i128>());
+
+        assert!(super::diff(&after.fs, &after.fs).is_empty());
+        let reverse = super::diff(&after.fs, &before.fs);
+        assert_eq!(reverse.entries.len(), diff.entries.len());
+        assert_eq!(reverse.delta("/"), -diff.delta("/"));
+
+        // the whole range of `usize`
+        let empty = BasicFileSystem::new();
+        let mut full = BasicFileSystem::new();
+        full.root().new_file("f".into(), usize::MAX, &mut full).unwrap();
+        assert_eq!(super::diff(&empty, &full).delta("/"), usize::MAX as i128);
+        assert_eq!(super::diff(&full, &empty).delta("/f"), -(usize::MAX as i128));
+    }
+}
@@ -0,0 +1,395 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::error::Error;
+use crate::fs::{BasicFileSystem, Directory, DirectoryHandle, File, FileKind, Metadata, Path};
+
+/// Order in which [emit] visits directories
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Order {
+    #[default]
+    DepthFirst,
+    BreadthFirst,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct EmitOptions {
+    pub order: Order,
+    /// Return to `/` and walk back down after every listing, instead of
+    /// taking the shortest route with `cd ..`
+    pub redundant_cd: bool,
+    /// List every directory twice
+    pub repeat_ls: bool,
+}
+
+/// Names of the directories from the root down to `dir`, excluding the root
+fn components(fs: &BasicFileSystem, dir: DirectoryHandle) -> Vec<&str> {
+    let mut components = vec![];
+    let mut view = fs.dir(dir);
+    while !view.is_root() {
+        components.push(view.name.as_str());
+        view = fs.dir(view.parent);
+    }
+    components.reverse();
+    components
+}
+
+/// Directories of `fs` in visiting order, children in listing order
+fn directories(fs: &BasicFileSystem, order: Order) -> Vec<DirectoryHandle> {
+    let children = |dir: DirectoryHandle| {
+        let mut dirs = fs.dir(dir).dirs.clone();
+        dirs.sort_by_key(|handle| handle.generation);
+        dirs
+    };
+    let mut visited = vec![];
+    match order {
+        Order::DepthFirst => {
+            let mut pending = vec![fs.root()];
+            while let Some(dir) = pending.pop() {
+                visited.push(dir);
+                pending.extend(children(dir).into_iter().rev());
+            }
+        }
+        Order::BreadthFirst => {
+            let mut pending = VecDeque::from([fs.root()]);
+            while let Some(dir) = pending.pop_front() {
+                visited.push(dir);
+                pending.extend(children(dir));
+            }
+        }
+    }
+    visited
+}
+
+/// `name` as a `cd` argument, in single quotes if a shell would split it
+fn quote(name: &str) -> Cow<'_, str> {
+    if name.contains([' ', '\t', '\'', '\\']) {
+        Cow::Owned(format!("'{}'", name.replace('\'', r"'\''")))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// Whether `name` reads back the same from an `ls` line and a `cd`: line
+/// breaks end the line, leading blanks are taken as the separator and `.`
+/// or `..` would be resolved
+fn representable(name: &str) -> bool {
+    !name.contains(['\r', '\n']) && !name.starts_with([' ', '\t']) && name != "." && name != ".."
+}
+
+/// Whether `metadata` reads back the same from an `ls -l` line, whose
+/// fields are separated by blanks
+fn representable_metadata(metadata: &Metadata) -> bool {
+    let word = |field: &str| !field.is_empty() && !field.contains(char::is_whitespace);
+    let mtime = metadata.mtime.split_once(' ').is_some_and(|(date, time)| word(date) && word(time));
+    let mode = metadata.mode.chars().count() >= 9 && word(&metadata.mode);
+    mode && word(&metadata.owner) && word(&metadata.group) && mtime
+}
+
+/// Listed for links without metadata, since `ls -l` always shows some
+fn placeholder(kind: &FileKind) -> Metadata {
+    let mode = if *kind == FileKind::Regular { "rw-r--r--" } else { "rwxrwxrwx" };
+    Metadata {
+        mode: mode.into(),
+        owner: "root".into(),
+        group: "root".into(),
+        mtime: "1970-01-01 00:00".into(),
+    }
+}
+
+/// A line of `ls -li`, for the entries that the short format would lose
+fn long_line(inode: usize, kind: char, links: usize, size: usize, metadata: &Metadata, name: &str) -> String {
+    let Metadata { mode, owner, group, mtime } = metadata;
+    format!("{} {}{} {} {} {} {} {} {}", inode, kind, mode, links, owner, group, size, mtime, name)
+}
+
+/// Line listing `dir`, and whether it is in the long format
+fn dir_line(fs: &BasicFileSystem, dir: &Directory) -> Result<(String, bool), Error> {
+    if !representable(&dir.name) {
+        return Err(Error::Unrepresentable(dir.handle.abspath(fs)));
+    }
+    match &dir.metadata {
+        None => Ok((format!("dir {}", dir.name), false)),
+        Some(metadata) if representable_metadata(metadata) => {
+            Ok((long_line(dir.handle.generation, 'd', 2, 4096, metadata, &dir.name), true))
+        }
+        Some(_) => Err(Error::Unrepresentable(dir.handle.abspath(fs))),
+    }
+}
+
+/// Line listing `file`, and whether it is in the long format
+fn file_line(fs: &BasicFileSystem, file: &File) -> Result<(String, bool), Error> {
+    let unrepresentable = || Error::Unrepresentable(file.handle.abspath(fs));
+    if !representable(&file.name) {
+        return Err(unrepresentable());
+    }
+    let links = file.handle.links(fs).len().max(1);
+    if file.metadata.is_none() && file.kind == FileKind::Regular && links == 1 {
+        return Ok((format!("{} {}", file.size, file.name), false));
+    }
+    let metadata = file.metadata.clone().unwrap_or_else(|| placeholder(&file.kind));
+    if !representable_metadata(&metadata) {
+        return Err(unrepresentable());
+    }
+    let line = match &file.kind {
+        FileKind::Regular => long_line(file.inode, '-', links, file.size, &metadata, &file.name),
+        FileKind::Symlink(target) => {
+            if file.name.contains(" -> ") || !representable(target) {
+                return Err(unrepresentable());
+            }
+            let name = format!("{} -> {}", file.name, target);
+            long_line(file.inode, 'l', links, file.size, &metadata, &name)
+        }
+    };
+    Ok((line, true))
+}
+
+fn ls(output: &mut String, fs: &BasicFileSystem, dir: DirectoryHandle) -> Result<(), Error> {
+    let view = fs.dir(dir);
+    // in listing order, so that parsing the transcript lists them in the same order
+    let mut lines: Vec<(usize, (String, bool))> = vec![];
+    for child in view.dirs.iter().map(|handle| fs.dir(*handle)) {
+        lines.push((child.handle.generation, dir_line(fs, child)?));
+    }
+    for file in view.files.iter().map(|handle| fs.file(*handle)) {
+        lines.push((file.handle.generation, file_line(fs, file)?));
+    }
+    lines.sort();
+    let long = lines.iter().any(|(_, (_, long))| *long);
+    writeln!(output, "{}", if long { "$ ls -li" } else { "$ ls" }).unwrap();
+    for (_, (line, _)) in lines {
+        writeln!(output, "{}", line).unwrap();
+    }
+    Ok(())
+}
+
+/// Write a `$ cd`/`$ ls` transcript that [crate::parse::parse] turns back
+/// into a filesystem with the same tree as `fs`, or
+/// [Error::Unrepresentable] if a name cannot be written in one. Entries
+/// with metadata, symbolic links and hard links are listed by `ls -li`, the
+/// links without metadata coming back with placeholder metadata.
+pub fn emit(fs: &BasicFileSystem, options: &EmitOptions) -> Result<String, Error> {
+    let mut output = String::new();
+    writeln!(output, "$ cd /").unwrap();
+    let mut cwd: Vec<&str> = vec![];
+    for (index, dir) in directories(fs, options.order).into_iter().enumerate() {
+        let target = components(fs, dir);
+        let common = if options.redundant_cd {
+            if index > 0 {
+                writeln!(output, "$ cd /").unwrap();
+            }
+            0
+        } else {
+            let common = cwd.iter().zip(&target).take_while(|(a, b)| a == b).count();
+            for _ in common..cwd.len() {
+                writeln!(output, "$ cd ..").unwrap();
+            }
+            common
+        };
+        for name in &target[common..] {
+            writeln!(output, "$ cd {}", quote(name)).unwrap();
+        }
+        cwd = target;
+        ls(&mut output, fs, dir)?;
+        if options.repeat_ls {
+            ls(&mut output, fs, dir)?;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fs::{Entry, FileHandle};
+    use crate::parse::parse;
+
+    /// Minimal linear congruential generator, good enough to shape test trees
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % bound
+        }
+    }
+
+    fn metadata(index: usize) -> Option<Metadata> {
+        Some(Metadata {
+            mode: "rw-r-----".into(),
+            owner: "alice".into(),
+            group: "staff".into(),
+            mtime: format!("2022-12-07 10:{:02}", index),
+        })
+    }
+
+    /// Directories, files, symbolic links and hard links, some with metadata.
+    /// Links always have metadata, which `ls -l` cannot leave out.
+    fn random_fs(seed: u64) -> BasicFileSystem {
+        let mut rng = Lcg(seed);
+        let mut fs = BasicFileSystem::new();
+        let mut dirs = vec![fs.root()];
+        let mut linkable = vec![];
+        for index in 0..40 {
+            let mut parent = dirs[rng.next(dirs.len())];
+            match rng.next(6) {
+                0 | 1 => {
+                    let dir = parent.new_directory(format!("d {}", index), &mut fs).unwrap();
+                    if rng.next(2) == 0 {
+                        dir.set_metadata(metadata(index), &mut fs).unwrap();
+                    }
+                    dirs.push(dir);
+                }
+                2 => {
+                    let link = parent.new_symlink(format!("l{}", index), format!("../t {}", index), &mut fs).unwrap();
+                    link.set_metadata(metadata(index), &mut fs).unwrap();
+                }
+                3 if !linkable.is_empty() => {
+                    let file = linkable[rng.next(linkable.len())];
+                    parent.new_hard_link(format!("h{}", index), file, &mut fs).unwrap();
+                }
+                _ => {
+                    let file = parent.new_file(format!("f{} it's.txt", index), rng.next(100_000), &mut fs).unwrap();
+                    if rng.next(2) == 0 {
+                        file.set_metadata(metadata(index), &mut fs).unwrap();
+                        linkable.push(file);
+                    }
+                }
+            }
+        }
+        fs
+    }
+
+    /// What a transcript records of each entry: its path, size, kind,
+    /// metadata and the names of its inode
+    fn entries(fs: &BasicFileSystem) -> Vec<String> {
+        fs.root()
+            .pre_order(fs)
+            .map(|(_, entry)| match entry {
+                Entry::Directory(dir) => format!("{} {:?}", dir.handle.abspath(fs), dir.metadata),
+                Entry::File(file) => {
+                    let mut links: Vec<String> = file.handle.links(fs).iter().map(|link| link.abspath(fs)).collect();
+                    links.sort();
+                    let path = file.handle.abspath(fs);
+                    format!("{} {} {:?} {:?} {:?}", path, file.size, file.kind, file.metadata, links)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emit_example() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut a = root.new_directory("a".into(), &mut fs).unwrap();
+        let _ = a.new_directory("e".into(), &mut fs).unwrap();
+        let _ = root.new_file("b.txt".into(), 14848514, &mut fs).unwrap();
+        let _ = root.new_directory("d".into(), &mut fs).unwrap();
+
+        assert_eq!(emit(&fs, &EmitOptions::default()).unwrap(),
+r#"$ cd /
+$ ls
+dir a
+14848514 b.txt
+dir d
+$ cd a
+$ ls
+dir e
+$ cd e
+$ ls
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+"#);
+
+        let options = EmitOptions { order: Order::BreadthFirst, redundant_cd: true, repeat_ls: false };
+        assert_eq!(emit(&fs, &options).unwrap(),
+r#"$ cd /
+$ ls
+dir a
+14848514 b.txt
+dir d
+$ cd /
+$ cd a
+$ ls
+dir e
+$ cd /
+$ cd d
+$ ls
+$ cd /
+$ cd a
+$ cd e
+$ ls
+"#);
+    }
+
+    /// Symbolic links, hard links and metadata are listed by `ls -li`
+    #[test]
+    fn emit_links() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut a = root.new_directory("a".into(), &mut fs).unwrap();
+        a.set_metadata(metadata(0), &mut fs).unwrap();
+        let f = a.new_file("f".into(), 100, &mut fs).unwrap();
+        root.new_hard_link("g".into(), f, &mut fs).unwrap();
+        let h = root.new_symlink("h".into(), "a/f".into(), &mut fs).unwrap();
+        let i = root.new_file("i".into(), 7, &mut fs).unwrap();
+        i.set_metadata(metadata(1), &mut fs).unwrap();
+
+        let transcript = emit(&fs, &EmitOptions::default()).unwrap();
+        let inode = |file: FileHandle| fs.file(file).inode;
+        assert_eq!(transcript, format!(
+r#"$ cd /
+$ ls -li
+{} drw-r----- 2 alice staff 4096 2022-12-07 10:00 a
+{} -rw-r--r-- 2 root root 100 1970-01-01 00:00 g
+{} lrwxrwxrwx 1 root root 3 1970-01-01 00:00 h -> a/f
+{} -rw-r----- 1 alice staff 7 2022-12-07 10:01 i
+$ cd a
+$ ls -li
+{} -rw-r--r-- 2 root root 100 1970-01-01 00:00 f
+"#, a.generation, inode(f), inode(h), inode(i), inode(f)));
+
+        // the links come back with the placeholder metadata
+        let state = parse(transcript).unwrap();
+        assert_eq!(state.fs.to_string(), fs.to_string());
+        let g = match state.fs.lookup(state.fs.root(), "/g") {
+            Ok(Entry::File(g)) => g,
+            _ => panic!("{}", state.fs),
+        };
+        assert_eq!(g.handle.links(&state.fs).len(), 2);
+        assert_eq!(g.metadata.as_ref().map(|metadata| metadata.owner.as_str()), Some("root"));
+    }
+
+    /// Names a transcript cannot hold are rejected instead of mangled
+    #[test]
+    fn emit_unrepresentable() {
+        for name in ["a\nb", " a", "..", "."] {
+            let mut fs = BasicFileSystem::new();
+            let mut root = fs.root();
+            let mut dir = root.new_directory("x y".into(), &mut fs).unwrap();
+            dir.new_file(name.into(), 1, &mut fs).unwrap();
+            let path = format!("/x y/{}", name);
+            assert_eq!(emit(&fs, &EmitOptions::default()), Err(Error::Unrepresentable(path)));
+        }
+    }
+
+    /// `parse(emit(fs))` reproduces the tree of `fs` for any options
+    #[test]
+    fn emit_round_trip() {
+        for seed in 0..20 {
+            let fs = random_fs(seed);
+            for order in [Order::DepthFirst, Order::BreadthFirst] {
+                for (redundant_cd, repeat_ls) in [(false, false), (true, false), (false, true), (true, true)] {
+                    let options = EmitOptions { order, redundant_cd, repeat_ls };
+                    let state = parse(emit(&fs, &options).unwrap()).unwrap();
+                    assert_eq!(state.fs.to_string(), fs.to_string());
+                    assert_eq!(entries(&state.fs), entries(&fs));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use crate::fs::{HandleError, LookupError};
+
+/// Errors raised while building or mutating a [crate::fs::BasicFileSystem],
+/// either directly or by replaying a transcript with [crate::parse::parse]
+#[derive(PartialEq, Eq, Debug)]
+pub enum Error {
+    /// A handle does not refer to a live entry of the filesystem
+    BadHandle(HandleError),
+    /// An entry with the same name already exists at this path
+    NameConflict(String),
+    /// A file was listed again with a different size
+    SizeConflict {
+        path: String,
+        listed: usize,
+        size: usize,
+    },
+    /// The operation is not permitted on the root directory
+    RootDirectory,
+    /// A directory cannot be moved into its own subtree
+    MoveIntoSubtree(String),
+    /// `cd` into a directory that does not exist
+    UnknownDirectory(String),
+    /// `cd ..` from the root directory
+    AboveRoot,
+    /// A path could not be resolved
+    Lookup(LookupError),
+    /// A line of the tree notation is not nested consistently, `line` is 1-based
+    BadIndentation {
+        line: usize,
+        text: String,
+    },
+    /// Reading a directory from disk failed
+    Io {
+        path: String,
+        kind: std::io::ErrorKind,
+    },
+    /// Line `line` of a transcript could not be read, `line` is 1-based
+    Read {
+        line: usize,
+        kind: std::io::ErrorKind,
+    },
+    /// A line of a transcript or tree could not be parsed, `line` and `column`
+    /// are 1-based
+    Malformed {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    /// A saved filesystem was written with another schema version
+    UnsupportedVersion(u32),
+    /// A saved filesystem could not be decoded or its links are inconsistent
+    BadSnapshot(String),
+    /// The filesystem cannot be modified
+    ReadOnly,
+    /// The root directory would grow to `size`, above the capacity
+    DiskFull {
+        capacity: usize,
+        size: usize,
+    },
+    /// A directory would grow to `size`, above its quota
+    QuotaExceeded {
+        path: String,
+        quota: usize,
+        size: usize,
+    },
+    /// A directory would grow above `usize::MAX` bytes
+    SizeOverflow(String),
+    /// An entry name cannot be written in a `$ cd`/`$ ls` transcript
+    Unrepresentable(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::BadHandle(error) => write!(f, "bad handle: {}", error),
+            Error::NameConflict(path) => write!(f, "{}: name already exists", path),
+            Error::SizeConflict { path, listed, size } => {
+                write!(f, "{}: listed with size {} and {}", path, listed, size)
+            }
+            Error::RootDirectory => write!(f, "operation not permitted on root directory"),
+            Error::MoveIntoSubtree(path) => write!(f, "{}: cannot move a directory into itself", path),
+            Error::UnknownDirectory(path) => write!(f, "{}: no such directory", path),
+            Error::AboveRoot => write!(f, "cannot cd above root directory"),
+            Error::Lookup(error) => write!(f, "{}", error),
+            Error::BadIndentation { line, text } => {
+                write!(f, "{}: inconsistent indentation {:?}", line, text)
+            }
+            Error::Io { path, kind } => write!(f, "{}: {}", path, kind),
+            Error::Read { line, kind } => write!(f, "{}: cannot read line: {}", line, kind),
+            Error::Malformed { line, column, text } => {
+                write!(f, "{}:{}: malformed line {:?}", line, column, text)
+            }
+            Error::UnsupportedVersion(version) => write!(f, "unsupported snapshot version {}", version),
+            Error::BadSnapshot(reason) => write!(f, "bad snapshot: {}", reason),
+            Error::ReadOnly => write!(f, "read-only filesystem"),
+            Error::DiskFull { capacity, size } => {
+                write!(f, "no space left on device: {} bytes of {}", size, capacity)
+            }
+            Error::QuotaExceeded { path, quota, size } => {
+                write!(f, "{}: disk quota exceeded: {} bytes of {}", path, size, quota)
+            }
+            Error::SizeOverflow(path) => write!(f, "{}: size too large", path),
+            Error::Unrepresentable(path) => write!(f, "{}: name cannot be written in a transcript", path),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::BadHandle(error) => Some(error),
+            Error::Lookup(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<HandleError> for Error {
+    fn from(error: HandleError) -> Self {
+        Error::BadHandle(error)
+    }
+}
+
+impl From<LookupError> for Error {
+    fn from(error: LookupError) -> Self {
+        Error::Lookup(error)
+    }
+}
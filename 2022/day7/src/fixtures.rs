@@ -0,0 +1,16 @@
+//! Transcripts shared by the unit tests
+
+use crate::parse::{parse, ShellState};
+
+/// The example transcript of the puzzle
+pub(crate) const EXAMPLE: &str = "$ cd /\n$ ls\ndir a\n14848514 b.txt\n8504156 c.dat\ndir d\n$ cd a\n$ ls\ndir e\n\
+    29116 f\n2557 g\n62596 h.lst\n$ cd e\n$ ls\n584 i\n$ cd ..\n$ cd ..\n$ cd d\n$ ls\n4060174 j\n\
+    8033020 d.log\n5626152 d.ext\n7214296 k\n";
+
+/// [EXAMPLE] replayed, with the current directory at `/d/`.
+///
+/// `/a/` is directory #1, `/d/` is #2 and `/a/e/` is #3; `/b.txt` is file #0
+/// and `/a/e/i` is file #5.
+pub(crate) fn example_state() -> ShellState {
+    parse(EXAMPLE.into()).unwrap()
+}
@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use crate::error::Error;
+use crate::fs::{FileSystem, Metadata, Path, Tree};
+
+/// Handle to a directory of a [FrozenFileSystem]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct FrozenDir(usize);
+
+/// Handle to a file of a [FrozenFileSystem]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct FrozenFile(usize);
+
+#[derive(Debug)]
+struct DirRecord {
+    name: String,
+    parent: usize,
+    dirs: Range<usize>,
+    files: Range<usize>,
+    /// Cumulative size, as reported by the frozen filesystem
+    size: usize,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Debug)]
+struct FileRecord {
+    name: String,
+    size: usize,
+    dir: usize,
+    metadata: Option<Metadata>,
+}
+
+/// Compact read-only copy of any [FileSystem].
+///
+/// Directories are stored level by level, so that the subdirectories and the
+/// files of a directory are contiguous ranges of two flat vectors: there are
+/// no tombstones, no generations and no per-directory listings. Mutations
+/// fail with [Error::ReadOnly].
+#[derive(Debug)]
+pub struct FrozenFileSystem {
+    dirs: Vec<DirRecord>,
+    files: Vec<FileRecord>,
+}
+
+impl FrozenFileSystem {
+    pub fn freeze<F: FileSystem>(fs: &F) -> Self {
+        let root = fs.root();
+        let mut sources = vec![root];
+        let mut dirs = vec![DirRecord {
+            name: fs.dir_name(root).into(),
+            parent: 0,
+            dirs: 0..0,
+            files: 0..0,
+            size: root.size(fs),
+            metadata: fs.dir_metadata(root).cloned(),
+        }];
+        let mut files = vec![];
+        let mut index = 0;
+        while let Some(source) = sources.get(index).copied() {
+            let first = sources.len();
+            for child in fs.subdirs(source) {
+                sources.push(child);
+                dirs.push(DirRecord {
+                    name: fs.dir_name(child).into(),
+                    parent: index,
+                    dirs: 0..0,
+                    files: 0..0,
+                    size: child.size(fs),
+                    metadata: fs.dir_metadata(child).cloned(),
+                });
+            }
+            dirs[index].dirs = first..sources.len();
+            let first = files.len();
+            files.extend(fs.files(source).into_iter().map(|file| FileRecord {
+                name: fs.file_name(file).into(),
+                size: file.size(fs),
+                dir: index,
+                metadata: fs.file_metadata(file).cloned(),
+            }));
+            dirs[index].files = first..files.len();
+            index += 1;
+        }
+        FrozenFileSystem { dirs, files }
+    }
+}
+
+impl FileSystem for FrozenFileSystem {
+    type Dir = FrozenDir;
+    type File = FrozenFile;
+
+    fn root(&self) -> FrozenDir {
+        FrozenDir(0)
+    }
+
+    fn parent(&self, dir: FrozenDir) -> Option<FrozenDir> {
+        (dir.0 != 0).then_some(FrozenDir(self.dirs[dir.0].parent))
+    }
+
+    fn subdirs(&self, dir: FrozenDir) -> Vec<FrozenDir> {
+        self.dirs[dir.0].dirs.clone().map(FrozenDir).collect()
+    }
+
+    fn files(&self, dir: FrozenDir) -> Vec<FrozenFile> {
+        self.dirs[dir.0].files.clone().map(FrozenFile).collect()
+    }
+
+    fn dir_name(&self, dir: FrozenDir) -> &str {
+        &self.dirs[dir.0].name
+    }
+
+    fn file_name(&self, file: FrozenFile) -> &str {
+        &self.files[file.0].name
+    }
+
+    fn file_dir(&self, file: FrozenFile) -> FrozenDir {
+        FrozenDir(self.files[file.0].dir)
+    }
+
+    fn dir_metadata(&self, dir: FrozenDir) -> Option<&Metadata> {
+        self.dirs[dir.0].metadata.as_ref()
+    }
+
+    fn file_metadata(&self, file: FrozenFile) -> Option<&Metadata> {
+        self.files[file.0].metadata.as_ref()
+    }
+
+    fn create_file(&mut self, _dir: FrozenDir, _name: String, _size: usize) -> Result<FrozenFile, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    fn create_directory(&mut self, _dir: FrozenDir, _name: String) -> Result<FrozenDir, Error> {
+        Err(Error::ReadOnly)
+    }
+}
+
+impl Path<FrozenFileSystem> for FrozenDir {
+    fn abspath(&self, fs: &FrozenFileSystem) -> String {
+        let mut names = vec![];
+        let mut dir = *self;
+        while let Some(parent) = fs.parent(dir) {
+            names.push(fs.dir_name(dir));
+            dir = parent;
+        }
+        let mut path = String::from("/");
+        for name in names.iter().rev() {
+            path.push_str(name);
+            path.push('/');
+        }
+        path
+    }
+}
+
+impl Tree<FrozenFileSystem> for FrozenDir {
+    fn size(&self, fs: &FrozenFileSystem) -> usize {
+        fs.dirs[self.0].size
+    }
+}
+
+impl Path<FrozenFileSystem> for FrozenFile {
+    fn abspath(&self, fs: &FrozenFileSystem) -> String {
+        let mut path = fs.file_dir(*self).abspath(fs);
+        path.push_str(fs.file_name(*self));
+        path
+    }
+}
+
+impl Tree<FrozenFileSystem> for FrozenFile {
+    fn size(&self, fs: &FrozenFileSystem) -> usize {
+        fs.files[self.0].size
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::EXAMPLE;
+    use crate::fs::{LookupError, Node};
+    use crate::parse::parse;
+
+    #[test]
+    fn frozen_example() {
+        let state = parse(format!("{}$ ls -l\n-rw-r--r-- 1 alice staff 7214296 2022-12-07 10:00 k\n", EXAMPLE)).unwrap();
+        let frozen = FrozenFileSystem::freeze(&state.fs);
+        let root = frozen.root();
+
+        assert_eq!(root.size(&frozen), 48381165);
+        assert_eq!(frozen.subdirs(root).iter().map(|dir| dir.abspath(&frozen)).collect::<Vec<_>>(), ["/a/", "/d/"]);
+        let Ok(Node::Directory(e)) = frozen.lookup_path(root, "/a/e/") else { panic!() };
+        assert_eq!((e.abspath(&frozen), e.size(&frozen)), ("/a/e/".into(), 584));
+        let Ok(Node::File(i)) = frozen.lookup_path(e, "../../a/./e/i") else { panic!() };
+        assert_eq!((i.abspath(&frozen), i.size(&frozen)), ("/a/e/i".into(), 584));
+        assert_eq!(frozen.lookup_path(e, "i/j"), Err(LookupError::NotADirectory("/a/e/i".into())));
+        assert_eq!(frozen.lookup_path(e, "j"), Err(LookupError::NotFound("/a/e/j".into())));
+        assert_eq!(frozen.parent(root), None);
+
+        let Ok(Node::File(k)) = frozen.lookup_path(root, "/d/k") else { panic!() };
+        assert_eq!(frozen.file_metadata(k).map(|metadata| metadata.owner.as_str()), Some("alice"));
+        assert_eq!(frozen.file_metadata(i), None);
+
+        let mut frozen = frozen;
+        assert_eq!(frozen.create_file(root, "x".into(), 1), Err(Error::ReadOnly));
+        assert_eq!(frozen.create_directory(e, "x".into()), Err(Error::ReadOnly));
+    }
+}
@@ -1,31 +1,143 @@
-pub trait Path {
-    fn abspath(&self, fs: &BasicFileSystem) -> String;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::cow::CowVec;
+use crate::error::Error;
+
+pub trait Path<F: FileSystem + ?Sized = BasicFileSystem> {
+    fn abspath(&self, fs: &F) -> String;
 }
 
-pub trait Tree {
-    fn size(&self, fs: &BasicFileSystem) -> usize;
+pub trait Tree<F: FileSystem + ?Sized = BasicFileSystem> {
+    fn size(&self, fs: &F) -> usize;
 }
 
+/// An entry of a [FileSystem], see [Entry] for the one of [BasicFileSystem]
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Node<D, F> {
+    Directory(D),
+    File(F),
+}
+
+/// A filesystem model the analyses can run on, whatever its storage.
+///
+/// Entries are designated by handles that are only meaningful for the
+/// filesystem that returned them. Sizes and paths come from [Tree] and [Path],
+/// which every handle implements.
+pub trait FileSystem {
+    type Dir: Copy + Eq + Hash + Debug + Path<Self> + Tree<Self>;
+    type File: Copy + Eq + Hash + Debug + Path<Self> + Tree<Self>;
+
+    fn root(&self) -> Self::Dir;
+
+    /// `None` for the root
+    fn parent(&self, dir: Self::Dir) -> Option<Self::Dir>;
+
+    fn subdirs(&self, dir: Self::Dir) -> Vec<Self::Dir>;
+
+    fn files(&self, dir: Self::Dir) -> Vec<Self::File>;
+
+    fn dir_name(&self, dir: Self::Dir) -> &str;
+
+    fn file_name(&self, file: Self::File) -> &str;
+
+    fn file_dir(&self, file: Self::File) -> Self::Dir;
+
+    fn dir_metadata(&self, dir: Self::Dir) -> Option<&Metadata>;
+
+    fn file_metadata(&self, file: Self::File) -> Option<&Metadata>;
+
+    /// Resolve `path` from `cwd` by name, with the rules of
+    /// [BasicFileSystem::lookup] except for symbolic links
+    fn lookup_path(&self, cwd: Self::Dir, path: &str) -> Result<Node<Self::Dir, Self::File>, LookupError> {
+        let mut cwd = if path.starts_with('/') { self.root() } else { cwd };
+        let mut components = path.split('/').filter(|component| !component.is_empty()).peekable();
+        while let Some(component) = components.next() {
+            match component {
+                "." => continue,
+                ".." => {
+                    cwd = self.parent(cwd).unwrap_or(cwd);
+                    continue;
+                }
+                _ => (),
+            }
+            if let Some(dir) = self.subdirs(cwd).into_iter().find(|dir| self.dir_name(*dir) == component) {
+                cwd = dir;
+                continue;
+            }
+            if let Some(file) = self.files(cwd).into_iter().find(|file| self.file_name(*file) == component) {
+                if components.peek().is_none() && !path.ends_with('/') {
+                    return Ok(Node::File(file));
+                }
+                return Err(LookupError::NotADirectory(file.abspath(self)));
+            }
+            let mut missing = cwd.abspath(self);
+            missing.push_str(component);
+            return Err(LookupError::NotFound(missing));
+        }
+        Ok(Node::Directory(cwd))
+    }
+
+    /// Add a file, or return the one already listed under `name`
+    fn create_file(&mut self, dir: Self::Dir, name: String, size: usize) -> Result<Self::File, Error>;
+
+    /// Add a directory, or return the one already listed under `name`
+    fn create_directory(&mut self, dir: Self::Dir, name: String) -> Result<Self::Dir, Error>;
+}
+
+/// Handle to a [File]. Besides the arena `index` it records which filesystem
+/// created it and the `generation` of the entry, so that a handle cannot be
+/// used on another filesystem or after its entry has been removed.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileHandle {
     pub index: usize,
+    pub generation: usize,
+    pub fs_id: usize,
 }
 
 impl FileHandle {
-    pub fn view<'a>(&'a self, fs: &'a BasicFileSystem) -> &File {
-        fs.file_entry(self.index).unwrap()
+    pub fn view<'a>(&self, fs: &'a BasicFileSystem) -> Result<&'a File, HandleError> {
+        fs.file_entry(*self)
     }
 
     pub fn directory(&self, fs: &BasicFileSystem) -> DirectoryHandle {
-        self.view(&fs).dir
+        fs.file(*self).dir
+    }
+
+    pub fn rename(&self, name: String, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        let dir = fs.file_entry(*self)?.dir;
+        fs.move_file(*self, dir, name)
+    }
+
+    pub fn move_to(&self, dest: DirectoryHandle, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        let name = fs.file_entry(*self)?.name.clone();
+        fs.move_file(*self, dest, name)
+    }
+
+    /// Every name of this file, this one included
+    pub fn links(&self, fs: &BasicFileSystem) -> Vec<FileHandle> {
+        fs.links(*self)
+    }
+
+    /// Metadata belongs to the inode, so it is shared by all hard links
+    pub fn set_metadata(&self, metadata: Option<Metadata>, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        fs.file_entry(*self)?;
+        for link in fs.links(*self) {
+            fs.file_entry_mut(link).unwrap().metadata = metadata.clone();
+        }
+        Ok(())
     }
 }
 
 impl Path for FileHandle {
     fn abspath(&self, fs: &BasicFileSystem) -> String {
-        let view = self.view(&fs);
-        let directory = self.directory(&fs);
-        let mut path = directory.abspath(&fs);
+        let view = fs.file(*self);
+        let directory = self.directory(fs);
+        let mut path = directory.abspath(fs);
         path.push_str(view.name.as_str());
         path
     }
@@ -33,39 +145,91 @@ impl Path for FileHandle {
 
 impl Tree for FileHandle {
     fn size(&self, fs: &BasicFileSystem) -> usize {
-        self.view(&fs).size
+        fs.file(*self).size
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Handle to a [Directory], see [FileHandle]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DirectoryHandle {
     pub index: usize,
+    pub generation: usize,
+    pub fs_id: usize,
 }
 
 impl DirectoryHandle {
-    pub fn view<'a>(&'a self, fs: &'a BasicFileSystem) -> &Directory {
-        fs.dir_entry(self.index).unwrap()
+    pub fn view<'a>(&self, fs: &'a BasicFileSystem) -> Result<&'a Directory, HandleError> {
+        fs.dir_entry(*self)
     }
 
-    pub fn new_file<'a>(
-        &'a mut self,
+    pub fn new_file(
+        &mut self,
         name: String,
         size: usize,
-        fs: &'a mut BasicFileSystem,
-    ) -> Result<FileHandle, &str> {
-        fs.new_file(name, size, self.index).map(|file| file.handle)
+        fs: &mut BasicFileSystem,
+    ) -> Result<FileHandle, Error> {
+        fs.new_file(name, size, *self).map(|file| file.handle)
+    }
+
+    pub fn new_directory(
+        &mut self,
+        name: String,
+        fs: &mut BasicFileSystem,
+    ) -> Result<DirectoryHandle, Error> {
+        fs.new_directory(name, *self).map(|dir| dir.handle)
+    }
+
+    /// Symbolic link called `name` to `target`, its size is the length of
+    /// the target as `ls -l` reports it
+    pub fn new_symlink(
+        &mut self,
+        name: String,
+        target: String,
+        fs: &mut BasicFileSystem,
+    ) -> Result<FileHandle, Error> {
+        fs.new_symlink(name, target, *self)
     }
 
-    pub fn new_directory<'a>(
-        &'a mut self,
+    /// Another name for `file`, which shares its inode, size and metadata
+    pub fn new_hard_link(
+        &mut self,
         name: String,
-        fs: &'a mut BasicFileSystem,
-    ) -> Result<DirectoryHandle, &str> {
-        fs.new_directory(name, self.index).map(|dir| dir.handle)
+        file: FileHandle,
+        fs: &mut BasicFileSystem,
+    ) -> Result<FileHandle, Error> {
+        fs.new_hard_link(name, file, *self)
+    }
+
+    pub fn set_metadata(&self, metadata: Option<Metadata>, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        fs.dir_entry_mut(*self)?.metadata = metadata;
+        Ok(())
+    }
+
+    /// Limit the size of the subtree, `None` for no limit. A quota below the
+    /// current size only rejects further growth.
+    pub fn set_quota(&self, quota: Option<usize>, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        fs.dir_entry_mut(*self)?.quota = quota;
+        Ok(())
     }
 
     pub fn parent(&self, fs: &BasicFileSystem) -> DirectoryHandle {
-        self.view(&fs).parent
+        fs.dir(*self).parent
+    }
+
+    pub fn rename(&self, name: String, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        let parent = fs.dir_entry(*self)?.parent;
+        fs.move_directory(*self, parent, name)
+    }
+
+    pub fn move_to(&self, dest: DirectoryHandle, fs: &mut BasicFileSystem) -> Result<(), Error> {
+        let name = fs.dir_entry(*self)?.name.clone();
+        fs.move_directory(*self, dest, name)
+    }
+
+    /// Resolve `path` relative to this directory, see [BasicFileSystem::lookup]
+    pub fn lookup<'a>(&self, path: &str, fs: &'a BasicFileSystem) -> Result<Entry<'a>, LookupError> {
+        fs.lookup(*self, path)
     }
 
     pub fn walk<DirectoryFn, FileFn>(
@@ -77,14 +241,12 @@ impl DirectoryHandle {
         DirectoryFn: Fn(&Directory),
         FileFn: Fn(&File),
     {
-        let view = self.view(&fs);
-        dir_fn(&view);
-        view.files
-            .iter()
-            .for_each(|file| file_fn(fs.file_entry(file.index).unwrap()));
-        view.dirs
-            .iter()
-            .for_each(|dir| dir.walk(&fs, dir_fn, file_fn));
+        for (_, entry) in self.pre_order(fs) {
+            match entry {
+                Entry::Directory(dir) => dir_fn(dir),
+                Entry::File(file) => file_fn(file),
+            }
+        }
     }
 
     pub fn fold<AccumTy, EntryFn>(
@@ -96,57 +258,83 @@ impl DirectoryHandle {
     where
         EntryFn: Fn(AccumTy, Entry) -> AccumTy,
     {
-        let view = self.view(&fs);
-        let accum = view
-            .dirs
-            .iter()
-            .fold(accum, |accum, handle| handle.fold(&fs, accum, entry_fn));
-        let accum = view.files.iter().fold(accum, |accum, handle| {
-            entry_fn(accum, Entry::File(handle.view(&fs)))
-        });
-        entry_fn(accum, Entry::Directory(view))
+        self.post_order(fs).fold(accum, |accum, (_, entry)| entry_fn(accum, entry))
     }
 }
 
 impl Path for DirectoryHandle {
     fn abspath(&self, fs: &BasicFileSystem) -> String {
-        let view = self.view(&fs);
-        if view.is_root() {
-            fs.sep().into()
-        } else {
-            let parent = self.parent(&fs);
-            let mut path = parent.abspath(&fs);
-            path.push_str(view.name.as_str());
+        let mut names = vec![];
+        let mut view = fs.dir(*self);
+        while !view.is_root() {
+            names.push(view.name.as_str());
+            view = fs.dir(view.parent);
+        }
+        let mut path = String::from(fs.sep());
+        for name in names.iter().rev() {
+            path.push_str(name);
             path.push_str(fs.sep());
-            path
         }
+        path
     }
 }
 
 impl Tree for DirectoryHandle {
     fn size(&self, fs: &BasicFileSystem) -> usize {
-        self.fold(&fs, 0usize, &|accum, entry| match entry {
-            Entry::File(file) => accum + file.size,
-            Entry::Directory(_) => accum,
-        })
+        fs.dir(*self).size
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// What a [File] entry is
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileKind {
+    #[default]
+    Regular,
+    /// Symbolic link to a path, a relative one starts from the directory
+    /// holding the link
+    Symlink(String),
+}
+
+/// Attributes listed by `ls -l`, kept as they were listed
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Metadata {
+    /// Permission bits without the entry type, e.g. `rw-r--r--`
+    pub mode: String,
+    pub owner: String,
+    pub group: String,
+    /// Modification time, e.g. `2022-12-07 10:00`
+    pub mtime: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct File {
     pub handle: FileHandle,
     pub name: String,
     pub size: usize,
     pub dir: DirectoryHandle,
+    pub kind: FileKind,
+    /// Shared by all hard links to the same file
+    pub inode: usize,
+    pub metadata: Option<Metadata>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Directory {
     pub handle: DirectoryHandle,
     pub name: String,
     pub dirs: Vec<DirectoryHandle>,
     pub files: Vec<FileHandle>,
     pub parent: DirectoryHandle,
+    /// Total size of all files in the subtree, kept up to date by
+    /// [BasicFileSystem::new_file]. Hard links to the same inode are counted once.
+    pub size: usize,
+    pub metadata: Option<Metadata>,
+    /// Largest size the subtree may grow to, see [DirectoryHandle::set_quota]
+    pub quota: Option<usize>,
 }
 
 impl Directory {
@@ -155,93 +343,933 @@ impl Directory {
     }
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum Entry<'a> {
     File(&'a File),
     Directory(&'a Directory),
 }
 
-#[derive(Debug)]
+impl<'a> Entry<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            Entry::File(file) => &file.name,
+            Entry::Directory(dir) => &dir.name,
+        }
+    }
+
+    /// Size of a file, or cumulative size of a directory
+    pub fn size(&self) -> usize {
+        match self {
+            Entry::File(file) => file.size,
+            Entry::Directory(dir) => dir.size,
+        }
+    }
+
+    /// Generation of the handle, larger for entries created later
+    pub fn generation(&self) -> usize {
+        match self {
+            Entry::File(file) => file.handle.generation,
+            Entry::Directory(dir) => dir.handle.generation,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum LookupError {
+    /// No entry exists at the given path
+    NotFound(String),
+    /// A file appears where a directory is expected
+    NotADirectory(String),
+    /// More than [MAX_SYMLINK_HOPS] symbolic links were followed, the path is
+    /// the link that was not followed anymore
+    SymlinkLoop(String),
+}
+
+impl std::fmt::Display for LookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LookupError::NotFound(path) => write!(f, "{}: no such file or directory", path),
+            LookupError::NotADirectory(path) => write!(f, "{}: not a directory", path),
+            LookupError::SymlinkLoop(path) => write!(f, "{}: too many levels of symbolic links", path),
+        }
+    }
+}
+
+impl std::error::Error for LookupError {}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum HandleError {
+    /// The handle was created by a different [BasicFileSystem]
+    ForeignFileSystem,
+    /// The entry the handle refers to has been removed
+    Stale,
+}
+
+impl std::fmt::Display for HandleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandleError::ForeignFileSystem => write!(f, "handle belongs to another filesystem"),
+            HandleError::Stale => write!(f, "handle refers to a removed entry"),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+impl FileSystem for BasicFileSystem {
+    type Dir = DirectoryHandle;
+    type File = FileHandle;
+
+    fn root(&self) -> DirectoryHandle {
+        BasicFileSystem::root(self)
+    }
+
+    fn parent(&self, dir: DirectoryHandle) -> Option<DirectoryHandle> {
+        let view = self.dir(dir);
+        (!view.is_root()).then_some(view.parent)
+    }
+
+    fn subdirs(&self, dir: DirectoryHandle) -> Vec<DirectoryHandle> {
+        self.dir(dir).dirs.clone()
+    }
+
+    fn files(&self, dir: DirectoryHandle) -> Vec<FileHandle> {
+        self.dir(dir).files.clone()
+    }
+
+    fn dir_name(&self, dir: DirectoryHandle) -> &str {
+        &self.dir(dir).name
+    }
+
+    fn file_name(&self, file: FileHandle) -> &str {
+        &self.file(file).name
+    }
+
+    fn file_dir(&self, file: FileHandle) -> DirectoryHandle {
+        self.file(file).dir
+    }
+
+    fn dir_metadata(&self, dir: DirectoryHandle) -> Option<&Metadata> {
+        self.dir(dir).metadata.as_ref()
+    }
+
+    fn file_metadata(&self, file: FileHandle) -> Option<&Metadata> {
+        self.file(file).metadata.as_ref()
+    }
+
+    /// Follows symbolic links, see [BasicFileSystem::lookup]
+    fn lookup_path(&self, cwd: DirectoryHandle, path: &str) -> Result<Node<DirectoryHandle, FileHandle>, LookupError> {
+        Ok(match self.lookup(cwd, path)? {
+            Entry::Directory(dir) => Node::Directory(dir.handle),
+            Entry::File(file) => Node::File(file.handle),
+        })
+    }
+
+    fn create_file(&mut self, dir: DirectoryHandle, name: String, size: usize) -> Result<FileHandle, Error> {
+        self.new_file(name, size, dir).map(|file| file.handle)
+    }
+
+    fn create_directory(&mut self, dir: DirectoryHandle, name: String) -> Result<DirectoryHandle, Error> {
+        self.new_directory(name, dir).map(|dir| dir.handle)
+    }
+}
+
+/// What [BasicFileSystem] does when a file is listed again with a different size
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConflictPolicy {
+    /// Keep the size that was seen first
+    #[default]
+    KeepFirst,
+    /// Overwrite with the size that was seen last
+    KeepLast,
+    /// Fail with [Error::SizeConflict]
+    Reject,
+}
+
+/// Number of symbolic links a lookup follows before giving up, as on Linux
+pub const MAX_SYMLINK_HOPS: usize = 40;
+
+/// Change of the cached size of a directory. Sizes use the whole range of
+/// `usize`, so the change is not a signed number.
+#[derive(Clone, Copy)]
+enum Delta {
+    Grow(usize),
+    Shrink(usize),
+}
+
+impl Delta {
+    /// Between the sizes `from` and `to`
+    fn between(from: usize, to: usize) -> Self {
+        if to >= from { Delta::Grow(to - from) } else { Delta::Shrink(from - to) }
+    }
+
+    /// `size` changed by `self`, which [BasicFileSystem::check_growth] made
+    /// sure fits
+    fn apply(self, size: usize) -> usize {
+        match self {
+            Delta::Grow(delta) => size + delta,
+            Delta::Shrink(delta) => size - delta,
+        }
+    }
+}
+
+/// Source of unique [BasicFileSystem] ids
+static NEXT_FS_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Size of a directory with a quota, see [BasicFileSystem::quota_usage]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct QuotaUsage {
+    pub dir: DirectoryHandle,
+    pub quota: usize,
+    pub used: usize,
+}
+
+impl QuotaUsage {
+    /// Bytes the directory may still grow by
+    pub fn available(&self) -> usize {
+        self.quota.saturating_sub(self.used)
+    }
+}
+
+/// Arena of all entries. Removed entries leave a `None` tombstone behind,
+/// the slot is reused by a later entry with a newer generation.
+///
+/// The arena is copy-on-write, see [BasicFileSystem::snapshot].
+#[derive(Debug, Clone)]
 pub struct BasicFileSystem {
-    pub dirs: Vec<Directory>,
-    pub files: Vec<File>,
+    pub dirs: CowVec<Option<Directory>>,
+    pub files: CowVec<Option<File>>,
+    id: usize,
+    /// Next generation, shared by directories and files so that generations
+    /// follow the order in which entries were created, and by snapshots so
+    /// that none of them hands out a generation twice
+    generation: Arc<AtomicUsize>,
+    free_dirs: CowVec<usize>,
+    free_files: CowVec<usize>,
+    conflict_policy: ConflictPolicy,
+    /// Largest size of the root directory, `None` for no limit
+    capacity: Option<usize>,
+    check_mutations: bool,
+    /// Names of every inode that has more than one
+    hard_links: Arc<HashMap<usize, Vec<FileHandle>>>,
+}
+
+impl Default for BasicFileSystem {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BasicFileSystem {
     pub fn new() -> Self {
-        let handle = DirectoryHandle { index: 0 };
+        Self::with_conflict_policy(ConflictPolicy::default())
+    }
+
+    pub fn with_conflict_policy(conflict_policy: ConflictPolicy) -> Self {
+        let id = NEXT_FS_ID.fetch_add(1, Ordering::Relaxed);
+        let handle = DirectoryHandle { index: 0, generation: 0, fs_id: id };
         let root = Directory {
             handle,
             name: "/".into(),
             parent: handle,
             dirs: [].into(),
             files: [].into(),
+            size: 0,
+            metadata: None,
+            quota: None,
         };
         BasicFileSystem {
-            dirs: [root].into(),
+            dirs: [Some(root)].into(),
             files: [].into(),
+            id,
+            generation: Arc::new(AtomicUsize::new(1)),
+            free_dirs: [].into(),
+            free_files: [].into(),
+            conflict_policy,
+            capacity: None,
+            check_mutations: cfg!(test),
+            hard_links: Arc::default(),
+        }
+    }
+
+    /// Rebuild a filesystem from the arena of another one, under a new id.
+    /// Handles are rewritten to the new id, links are not checked.
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_arena(
+        mut dirs: Vec<Option<Directory>>,
+        mut files: Vec<Option<File>>,
+        generation: usize,
+        conflict_policy: ConflictPolicy,
+    ) -> Self {
+        let fs_id = NEXT_FS_ID.fetch_add(1, Ordering::Relaxed);
+        for dir in dirs.iter_mut().flatten() {
+            dir.handle.fs_id = fs_id;
+            dir.parent.fs_id = fs_id;
+            dir.dirs.iter_mut().for_each(|handle| handle.fs_id = fs_id);
+            dir.files.iter_mut().for_each(|handle| handle.fs_id = fs_id);
+        }
+        for file in files.iter_mut().flatten() {
+            file.handle.fs_id = fs_id;
+            file.dir.fs_id = fs_id;
+        }
+        let mut fs = BasicFileSystem {
+            dirs: dirs.into(),
+            files: files.into(),
+            id: fs_id,
+            generation: Arc::new(AtomicUsize::new(generation)),
+            free_dirs: [].into(),
+            free_files: [].into(),
+            conflict_policy,
+            capacity: None,
+            check_mutations: cfg!(test),
+            hard_links: Arc::default(),
+        };
+        fs.rebuild_free_slots();
+        fs.rebuild_hard_links();
+        fs
+    }
+
+    /// Generation of the next entry created
+    pub(crate) fn generation(&self) -> usize {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Copy of the filesystem in constant time. The copy shares the arena
+    /// with `self`, a mutation of either only copies the chunks of
+    /// [CowVec] it writes to.
+    ///
+    /// Handles of the entries that exist when the snapshot is taken are valid
+    /// in both filesystems. The copies share the counter of generations, so
+    /// an entry created afterwards in one of them gets a handle that the
+    /// others reject with [HandleError::Stale], even if it uses the same slot.
+    pub fn snapshot(&self) -> BasicFileSystem {
+        self.clone()
+    }
+
+    /// Limit the size of the root directory, `None` for no limit. Operations
+    /// that would grow the filesystem above it fail with [Error::DiskFull],
+    /// a capacity below the current size only rejects further growth.
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Every directory with a quota and its current size, in pre-order
+    pub fn quota_usage(&self) -> Vec<QuotaUsage> {
+        self.root()
+            .pre_order(self)
+            .filter_map(|(_, entry)| match entry {
+                Entry::Directory(dir) => dir.quota.map(|quota| QuotaUsage { dir: dir.handle, quota, used: dir.size }),
+                Entry::File(_) => None,
+            })
+            .collect()
+    }
+
+    /// Run [crate::fsck::check] after every mutation and panic on the first
+    /// violation, in debug builds only. On by default in this crate's tests.
+    pub fn check_mutations(&mut self, enabled: bool) {
+        self.check_mutations = enabled;
+    }
+
+    fn after_mutation(&self) {
+        if cfg!(debug_assertions) && self.check_mutations {
+            let report = crate::fsck::check(self);
+            assert!(report.is_ok(), "filesystem corrupted:\n{}", report);
         }
     }
 
-    fn sep(&self) -> &str {
+    pub(crate) fn free_slots(&self) -> (&CowVec<usize>, &CowVec<usize>) {
+        (&self.free_dirs, &self.free_files)
+    }
+
+    /// Make every empty slot, and only those, available for reuse
+    pub(crate) fn rebuild_free_slots(&mut self) {
+        self.free_dirs = (0..self.dirs.len()).filter(|index| self.dirs[*index].is_none()).collect();
+        self.free_files = (0..self.files.len()).filter(|index| self.files[*index].is_none()).collect();
+    }
+
+    pub(crate) fn hard_links(&self) -> &HashMap<usize, Vec<FileHandle>> {
+        &self.hard_links
+    }
+
+    /// Group the files that share an inode, in slot order
+    pub(crate) fn rebuild_hard_links(&mut self) {
+        let mut inodes: HashMap<usize, Vec<FileHandle>> = HashMap::new();
+        for file in self.files.iter().flatten() {
+            inodes.entry(file.inode).or_default().push(file.handle);
+        }
+        inodes.retain(|_, links| links.len() > 1);
+        self.hard_links = Arc::new(inodes);
+    }
+
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    /// Unique id of this filesystem, recorded in every handle it creates
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn sep(&self) -> &str {
         "/"
     }
 
     pub fn root(&self) -> DirectoryHandle {
-        DirectoryHandle { index: 0 }
+        DirectoryHandle { index: 0, generation: 0, fs_id: self.id }
+    }
+
+    /// Resolve an absolute or relative `path` starting from `cwd`.
+    ///
+    /// Empty components and `.` are ignored, `..` moves to the parent (the
+    /// parent of `/` is `/` itself). A trailing separator requires the last
+    /// component to be a directory. Symbolic links are followed, up to
+    /// [MAX_SYMLINK_HOPS] of them.
+    pub fn lookup(&self, cwd: DirectoryHandle, path: &str) -> Result<Entry<'_>, LookupError> {
+        self.resolve(cwd, path, true)
+    }
+
+    /// Like [BasicFileSystem::lookup], but a symbolic link in last position is
+    /// returned itself instead of being followed, as `lstat` does
+    pub fn lookup_link(&self, cwd: DirectoryHandle, path: &str) -> Result<Entry<'_>, LookupError> {
+        self.resolve(cwd, path, false)
+    }
+
+    fn resolve(&self, cwd: DirectoryHandle, path: &str, follow_last: bool) -> Result<Entry<'_>, LookupError> {
+        let mut cwd = if path.starts_with(self.sep()) { self.root() } else { cwd };
+        let must_be_dir = path.ends_with(self.sep());
+        // remaining components, the next one last
+        let mut pending: Vec<&str> = path.rsplit(self.sep()).filter(|component| !component.is_empty()).collect();
+        let mut hops = 0;
+        while let Some(component) = pending.pop() {
+            if component == "." {
+                continue;
+            }
+            if component == ".." {
+                cwd = cwd.parent(self);
+                continue;
+            }
+            let view = self.dir(cwd);
+            if let Some(dir) = view.dirs.iter().find(|dir| self.dir(**dir).name == component) {
+                cwd = *dir;
+                continue;
+            }
+            if let Some(file) = view.files.iter().find(|file| self.file(**file).name == component) {
+                let file = self.file(*file);
+                let last = pending.is_empty();
+                match &file.kind {
+                    FileKind::Symlink(target) if !last || must_be_dir || follow_last => {
+                        hops += 1;
+                        if hops > MAX_SYMLINK_HOPS {
+                            return Err(LookupError::SymlinkLoop(file.handle.abspath(self)));
+                        }
+                        if target.starts_with(self.sep()) {
+                            cwd = self.root();
+                        }
+                        pending.extend(target.rsplit(self.sep()).filter(|component| !component.is_empty()));
+                        continue;
+                    }
+                    _ if last && !must_be_dir => return Ok(Entry::File(file)),
+                    _ => return Err(LookupError::NotADirectory(file.handle.abspath(self))),
+                }
+            }
+            let mut missing = cwd.abspath(self);
+            missing.push_str(component);
+            return Err(LookupError::NotFound(missing));
+        }
+        Ok(Entry::Directory(self.dir(cwd)))
+    }
+
+    fn dir_entry(&self, handle: DirectoryHandle) -> Result<&Directory, HandleError> {
+        if handle.fs_id != self.id {
+            return Err(HandleError::ForeignFileSystem);
+        }
+        self.dirs
+            .get(handle.index)
+            .and_then(Option::as_ref)
+            .filter(|dir| dir.handle == handle)
+            .ok_or(HandleError::Stale)
+    }
+
+    fn dir_entry_mut(&mut self, handle: DirectoryHandle) -> Result<&mut Directory, HandleError> {
+        self.dir_entry(handle)?;
+        Ok(self.dirs[handle.index].as_mut().unwrap())
+    }
+
+    fn file_entry(&self, handle: FileHandle) -> Result<&File, HandleError> {
+        if handle.fs_id != self.id {
+            return Err(HandleError::ForeignFileSystem);
+        }
+        self.files
+            .get(handle.index)
+            .and_then(Option::as_ref)
+            .filter(|file| file.handle == handle)
+            .ok_or(HandleError::Stale)
     }
 
-    fn dir_entry(&self, index: usize) -> Option<&Directory> {
-        self.dirs.get(index)
+    fn file_entry_mut(&mut self, handle: FileHandle) -> Result<&mut File, HandleError> {
+        self.file_entry(handle)?;
+        Ok(self.files[handle.index].as_mut().unwrap())
     }
 
-    fn file_entry(&self, index: usize) -> Option<&File> {
-        self.files.get(index)
+    /// Directory behind a handle that is known to be valid, e.g. the child or
+    /// parent of another entry
+    pub(crate) fn dir(&self, handle: DirectoryHandle) -> &Directory {
+        self.dir_entry(handle)
+            .unwrap_or_else(|error| panic!("{:?}: {}", handle, error))
     }
 
-    fn new_file(&mut self, name: String, size: usize, directory: usize) -> Result<&File, &str> {
-        let parent = self.dirs.get(directory);
-        if parent.is_none() {
-            return Err("Bad directory index");
+    /// File behind a handle that is known to be valid, see [BasicFileSystem::dir]
+    pub(crate) fn file(&self, handle: FileHandle) -> &File {
+        self.file_entry(handle)
+            .unwrap_or_else(|error| panic!("{:?}: {}", handle, error))
+    }
+
+    pub(crate) fn alloc_dir(&mut self) -> DirectoryHandle {
+        let index = self.free_dirs.pop().unwrap_or_else(|| {
+            self.dirs.push(None);
+            self.dirs.len() - 1
+        });
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        DirectoryHandle { index, generation, fs_id: self.id }
+    }
+
+    fn alloc_file(&mut self) -> FileHandle {
+        let index = self.free_files.pop().unwrap_or_else(|| {
+            self.files.push(None);
+            self.files.len() - 1
+        });
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        FileHandle { index, generation, fs_id: self.id }
+    }
+
+    /// Whether `directory` already holds a file or a directory called `name`
+    fn contains_name(&self, directory: DirectoryHandle, name: &str) -> bool {
+        let view = self.dir(directory);
+        view.dirs.iter().any(|handle| self.dir(*handle).name == name)
+            || view.files.iter().any(|handle| self.file(*handle).name == name)
+    }
+
+    /// Absolute path of an entry called `name` inside `directory`
+    fn child_path(&self, directory: DirectoryHandle, name: &str) -> String {
+        let mut path = directory.abspath(self);
+        path.push_str(name);
+        path
+    }
+
+    fn new_file(&mut self, name: String, size: usize, directory: DirectoryHandle) -> Result<&File, Error> {
+        let parent = self.dir_entry(directory)?;
+        if parent.dirs.iter().any(|handle| self.dir(*handle).name == name) {
+            return Err(Error::NameConflict(self.child_path(directory, &name)));
+        }
+        let mut files = parent.files.iter();
+        if let Some(handle) = files.find(|handle| self.file(**handle).name == name).copied() {
+            // a symbolic link is not the file it points to
+            if self.file(handle).kind != FileKind::Regular {
+                return Err(Error::NameConflict(self.child_path(directory, &name)));
+            }
+            let listed = self.file(handle).size;
+            if listed != size {
+                match self.conflict_policy {
+                    ConflictPolicy::KeepFirst => (),
+                    ConflictPolicy::KeepLast => {
+                        let growth = size.saturating_sub(listed);
+                        self.check_growth(self.link_ancestors(handle).into_iter().map(|dir| (dir, growth)))?;
+                        self.resize(handle, size);
+                        self.after_mutation();
+                    }
+                    ConflictPolicy::Reject => {
+                        let path = self.child_path(directory, &name);
+                        return Err(Error::SizeConflict { path, listed, size });
+                    }
+                }
+            }
+            return Ok(self.file(handle));
+        }
+        self.check_growth(self.ancestors(directory).map(|dir| (dir, size)))?;
+        let handle = self.insert_file(directory, name, size, FileKind::Regular);
+        self.charge(handle, Delta::Grow(size));
+        self.after_mutation();
+        Ok(self.file(handle))
+    }
+
+    fn new_symlink(&mut self, name: String, target: String, directory: DirectoryHandle) -> Result<FileHandle, Error> {
+        self.dir_entry(directory)?;
+        let kind = FileKind::Symlink(target);
+        if let Some(handle) = self.child_file(directory, &name) {
+            if self.file(handle).kind == kind {
+                return Ok(handle);
+            }
+        }
+        if self.contains_name(directory, &name) {
+            return Err(Error::NameConflict(self.child_path(directory, &name)));
         }
-        let mut files = parent.unwrap().files.iter();
-        if let Some(handle) = files.find(|handle| handle.view(&self).name == name) {
-            return self.file_entry(handle.index).ok_or("Invalid file index")
+        let FileKind::Symlink(target) = &kind else { unreachable!() };
+        let size = target.len();
+        self.check_growth(self.ancestors(directory).map(|dir| (dir, size)))?;
+        let handle = self.insert_file(directory, name, size, kind);
+        self.charge(handle, Delta::Grow(size));
+        self.after_mutation();
+        Ok(handle)
+    }
+
+    fn new_hard_link(&mut self, name: String, file: FileHandle, directory: DirectoryHandle) -> Result<FileHandle, Error> {
+        let view = self.file_entry(file)?;
+        let (size, kind, inode, metadata) = (view.size, view.kind.clone(), view.inode, view.metadata.clone());
+        self.dir_entry(directory)?;
+        if let Some(handle) = self.child_file(directory, &name) {
+            if self.file(handle).inode == inode {
+                return Ok(handle);
+            }
+        }
+        if self.contains_name(directory, &name) {
+            return Err(Error::NameConflict(self.child_path(directory, &name)));
         }
-        let index = self.files.len();
-        let handle = FileHandle { index };
-        let parent = self.dirs.get_mut(directory).unwrap();
-        self.files.push(File {
+        self.check_growth(self.uncounted(directory, file, false).into_iter().map(|dir| (dir, size)))?;
+        let handle = self.insert_file(directory, name, size, kind);
+        let view = self.file_entry_mut(handle).unwrap();
+        view.inode = inode;
+        view.metadata = metadata;
+        Arc::make_mut(&mut self.hard_links).entry(inode).or_insert_with(|| vec![file]);
+        self.attach(handle);
+        self.after_mutation();
+        Ok(handle)
+    }
+
+    /// File called `name` in `directory`, if any
+    fn child_file(&self, directory: DirectoryHandle, name: &str) -> Option<FileHandle> {
+        self.dir(directory).files.iter().copied().find(|handle| self.file(*handle).name == name)
+    }
+
+    /// Add a file with a new inode to `directory`, without counting its size
+    fn insert_file(&mut self, directory: DirectoryHandle, name: String, size: usize, kind: FileKind) -> FileHandle {
+        let handle = self.alloc_file();
+        self.dir_entry_mut(directory).unwrap().files.push(handle);
+        self.files[handle.index] = Some(File {
             name,
             size,
             handle,
-            dir: parent.handle,
+            dir: directory,
+            kind,
+            // generations are never handed out twice
+            inode: handle.generation,
+            metadata: None,
         });
-        parent.files.push(handle);
-        self.file_entry(index).ok_or("Invalid file index")
+        handle
+    }
+
+    pub(crate) fn links(&self, file: FileHandle) -> Vec<FileHandle> {
+        let inode = self.file(file).inode;
+        self.hard_links.get(&inode).cloned().unwrap_or_else(|| vec![file])
+    }
+
+    /// `directory` and its ancestors, up to the root
+    fn ancestors(&self, directory: DirectoryHandle) -> impl Iterator<Item = DirectoryHandle> + '_ {
+        std::iter::successors(Some(directory), |handle| {
+            let view = self.dir(*handle);
+            (!view.is_root()).then_some(view.parent)
+        })
+    }
+
+    /// `directory` and its ancestors that do not count the inode of `file`
+    /// through one of its names, other than `file` itself if `except_file`
+    fn uncounted(&self, directory: DirectoryHandle, file: FileHandle, except_file: bool) -> Vec<DirectoryHandle> {
+        let counted: HashSet<DirectoryHandle> = self
+            .links(file)
+            .into_iter()
+            .filter(|link| !except_file || *link != file)
+            .flat_map(|link| self.ancestors(self.file(link).dir))
+            .collect();
+        self.ancestors(directory).take_while(|dir| !counted.contains(dir)).collect()
+    }
+
+    /// Every directory that holds a name of `file`, directly or not
+    fn link_ancestors(&self, file: FileHandle) -> Vec<DirectoryHandle> {
+        let mut seen = HashSet::new();
+        self.links(file)
+            .iter()
+            .flat_map(|link| self.ancestors(self.file(*link).dir))
+            .filter(|dir| seen.insert(*dir))
+            .collect()
+    }
+
+    /// Fail if growing each directory by its number of bytes would take it
+    /// above its quota, the root above the capacity or any of them above
+    /// `usize::MAX`
+    fn check_growth(&self, growth: impl IntoIterator<Item = (DirectoryHandle, usize)>) -> Result<(), Error> {
+        for (handle, delta) in growth {
+            if delta == 0 {
+                continue;
+            }
+            let view = self.dir(handle);
+            let size = view.size.checked_add(delta).ok_or_else(|| Error::SizeOverflow(handle.abspath(self)))?;
+            if let Some(quota) = view.quota.filter(|quota| size > *quota) {
+                return Err(Error::QuotaExceeded { path: handle.abspath(self), quota, size });
+            }
+            if let Some(capacity) = self.capacity.filter(|capacity| view.is_root() && size > *capacity) {
+                return Err(Error::DiskFull { capacity, size });
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `delta` for `file` to the cached size of its directory and of the
+    /// ancestors that do not already count another hard link to its inode
+    fn charge(&mut self, file: FileHandle, delta: Delta) {
+        let view = self.file(file);
+        for handle in self.uncounted(view.dir, file, true) {
+            let dir = self.dir_entry_mut(handle).unwrap();
+            dir.size = delta.apply(dir.size);
+        }
+    }
+
+    /// Record `file` as a hard link to its inode and count it
+    fn attach(&mut self, file: FileHandle) {
+        let view = self.file(file);
+        let (inode, size) = (view.inode, view.size);
+        let links = Arc::make_mut(&mut self.hard_links).entry(inode).or_default();
+        if !links.contains(&file) {
+            links.push(file);
+        }
+        self.charge(file, Delta::Grow(size));
+    }
+
+    /// Uncount `file` and forget it as a hard link. Its inode may be left with
+    /// fewer than two recorded names, see [BasicFileSystem::prune_links].
+    fn detach(&mut self, file: FileHandle) {
+        let view = self.file(file);
+        let (inode, size) = (view.inode, view.size);
+        self.charge(file, Delta::Shrink(size));
+        if let Some(links) = Arc::make_mut(&mut self.hard_links).get_mut(&inode) {
+            links.retain(|link| *link != file);
+        }
     }
 
-    fn new_directory(&mut self, name: String, parent: usize) -> Result<&Directory, &str> {
-        let parent_directory = self.dirs.get(parent);
-        if parent_directory.is_none() {
-            return Err("Bad parent index");
+    fn prune_links(&mut self, inode: usize) {
+        if self.hard_links.get(&inode).is_some_and(|links| links.len() < 2) {
+            Arc::make_mut(&mut self.hard_links).remove(&inode);
         }
-        let mut dirs = parent_directory.unwrap().dirs.iter();
-        if let Some(handle) = dirs.find(|handle| handle.view(&self).name == name) {
-            return self.dir_entry(handle.index).ok_or("Invalid directory index");
+    }
+
+    /// Files below `dir` that have other names
+    fn linked_files(&self, dir: DirectoryHandle) -> Vec<FileHandle> {
+        if self.hard_links.is_empty() {
+            return vec![];
+        }
+        dir.pre_order(self)
+            .filter_map(|(_, entry)| match entry {
+                Entry::File(file) if self.hard_links.contains_key(&file.inode) => Some(file.handle),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Set the size of `file` and of its other names, counting the change
+    /// once in every directory that holds any of them
+    fn resize(&mut self, file: FileHandle, size: usize) {
+        let delta = Delta::between(self.file(file).size, size);
+        for handle in self.link_ancestors(file) {
+            let dir = self.dir_entry_mut(handle).unwrap();
+            dir.size = delta.apply(dir.size);
+        }
+        for link in self.links(file) {
+            self.file_entry_mut(link).unwrap().size = size;
+        }
+    }
+
+    /// Add `delta` to the cached size of `directory` and all of its ancestors
+    fn propagate_size(&mut self, directory: DirectoryHandle, delta: Delta) {
+        let mut handle = directory;
+        loop {
+            let dir = self.dir_entry_mut(handle).unwrap();
+            dir.size = delta.apply(dir.size);
+            if dir.is_root() {
+                break;
+            }
+            handle = dir.parent;
+        }
+    }
+
+    /// Total size of every directory, computed in a single pass over the arena
+    pub fn dir_sizes(&self) -> Vec<(DirectoryHandle, usize)> {
+        self.dirs.iter().flatten().map(|dir| (dir.handle, dir.size)).collect()
+    }
+
+    /// Remove `file` from its directory, returning the number of bytes freed,
+    /// which is 0 while the file has other names
+    pub fn remove_file(&mut self, file: FileHandle) -> Result<usize, Error> {
+        self.file_entry(file)?;
+        let before = self.dir(self.root()).size;
+        self.detach(file);
+        let removed = self.files[file.index].take().unwrap();
+        self.prune_links(removed.inode);
+        self.free_files.push(file.index);
+        self.dir_entry_mut(removed.dir).unwrap().files.retain(|handle| *handle != file);
+        self.after_mutation();
+        Ok(before - self.dir(self.root()).size)
+    }
+
+    /// Recursively remove `dir` and everything below it, returning the number
+    /// of bytes freed
+    pub fn remove_directory(&mut self, dir: DirectoryHandle) -> Result<usize, Error> {
+        let view = self.dir_entry(dir)?;
+        if view.is_root() {
+            return Err(Error::RootDirectory);
+        }
+        let before = self.dir(self.root()).size;
+        let linked = self.linked_files(dir);
+        for file in &linked {
+            self.detach(*file);
+        }
+        for file in &linked {
+            self.prune_links(self.file(*file).inode);
+        }
+        let view = self.dir(dir);
+        let (parent, size) = (view.parent, view.size);
+        self.dir_entry_mut(parent).unwrap().dirs.retain(|handle| *handle != dir);
+        self.propagate_size(parent, Delta::Shrink(size));
+
+        let mut pending = vec![dir];
+        while let Some(handle) = pending.pop() {
+            let removed = self.dirs[handle.index].take().unwrap();
+            self.free_dirs.push(handle.index);
+            for file in removed.files {
+                self.files[file.index] = None;
+                self.free_files.push(file.index);
+            }
+            pending.extend(removed.dirs);
+        }
+        self.after_mutation();
+        Ok(before - self.dir(self.root()).size)
+    }
+
+    /// Move `file` into `dest` under `name`, which also covers renaming in
+    /// place and moving without renaming
+    pub fn move_file(&mut self, file: FileHandle, dest: DirectoryHandle, name: String) -> Result<(), Error> {
+        let view = self.file_entry(file)?;
+        let (source, size) = (view.dir, view.size);
+        self.dir_entry(dest)?;
+        if dest == source && view.name == name {
+            return Ok(());
         }
-        let index = self.dirs.len();
-        let handle = DirectoryHandle { index };
-        let parent_directory = self.dirs.get_mut(parent).unwrap();
-        let directory = Directory {
+        if self.contains_name(dest, &name) {
+            return Err(Error::NameConflict(self.child_path(dest, &name)));
+        }
+        if dest != source {
+            // the common ancestors keep the same size
+            let kept: HashSet<DirectoryHandle> = self.ancestors(source).collect();
+            let growth = self.uncounted(dest, file, true).into_iter().take_while(|dir| !kept.contains(dir));
+            self.check_growth(growth.map(|dir| (dir, size)))?;
+            self.charge(file, Delta::Shrink(size));
+            self.dir_entry_mut(source).unwrap().files.retain(|other| *other != file);
+            self.dir_entry_mut(dest).unwrap().files.push(file);
+            self.file_entry_mut(file).unwrap().dir = dest;
+            self.charge(file, Delta::Grow(size));
+        }
+        self.file_entry_mut(file).unwrap().name = name;
+        self.after_mutation();
+        Ok(())
+    }
+
+    /// Move `dir` into `dest` under `name`, see [BasicFileSystem::move_file]
+    pub fn move_directory(&mut self, dir: DirectoryHandle, dest: DirectoryHandle, name: String) -> Result<(), Error> {
+        let view = self.dir_entry(dir)?;
+        if view.is_root() {
+            return Err(Error::RootDirectory);
+        }
+        let source = view.parent;
+        let mut ancestor = self.dir_entry(dest)?;
+        if dest == source && view.name == name {
+            return Ok(());
+        }
+        loop {
+            if ancestor.handle == dir {
+                return Err(Error::MoveIntoSubtree(dir.abspath(self)));
+            }
+            if ancestor.is_root() {
+                break;
+            }
+            ancestor = self.dir(ancestor.parent);
+        }
+        if self.contains_name(dest, &name) {
+            return Err(Error::NameConflict(self.child_path(dest, &name)));
+        }
+        if dest != source {
+            self.check_growth(self.subtree_growth(dir, dest))?;
+            // hard links are counted again once the subtree is in place
+            let linked = self.linked_files(dir);
+            for file in &linked {
+                self.detach(*file);
+            }
+            let size = self.dir(dir).size;
+            self.dir_entry_mut(source).unwrap().dirs.retain(|other| *other != dir);
+            self.propagate_size(source, Delta::Shrink(size));
+            self.dir_entry_mut(dest).unwrap().dirs.push(dir);
+            self.propagate_size(dest, Delta::Grow(size));
+            self.dir_entry_mut(dir).unwrap().parent = dest;
+            for file in linked {
+                self.attach(file);
+            }
+        }
+        self.dir_entry_mut(dir).unwrap().name = name;
+        self.after_mutation();
+        Ok(())
+    }
+
+    /// Directories that grow when `dir` moves into `dest`, with their growth:
+    /// the size of `dir` less its files already counted through other names
+    fn subtree_growth(&self, dir: DirectoryHandle, dest: DirectoryHandle) -> Vec<(DirectoryHandle, usize)> {
+        let linked = self.linked_files(dir);
+        let inside: HashSet<FileHandle> = linked.iter().copied().collect();
+        let mut inodes = HashSet::new();
+        // size of every inode linked from outside the subtree, with the
+        // directories that count it from there
+        let outside: Vec<(usize, HashSet<DirectoryHandle>)> = linked
+            .iter()
+            .filter(|file| inodes.insert(self.file(**file).inode))
+            .map(|file| {
+                let links = self.links(*file).into_iter().filter(|link| !inside.contains(link));
+                (self.file(*file).size, links.flat_map(|link| self.ancestors(self.file(link).dir)).collect())
+            })
+            .collect();
+        let kept: HashSet<DirectoryHandle> = self.ancestors(self.dir(dir).parent).collect();
+        let size = self.dir(dir).size;
+        self.ancestors(dest)
+            .take_while(|ancestor| !kept.contains(ancestor))
+            .map(|ancestor| {
+                let counted: usize = outside.iter().filter(|(_, dirs)| dirs.contains(&ancestor)).map(|(size, _)| size).sum();
+                (ancestor, size - counted)
+            })
+            .collect()
+    }
+
+    fn new_directory(&mut self, name: String, parent: DirectoryHandle) -> Result<&Directory, Error> {
+        let parent_directory = self.dir_entry(parent)?;
+        if parent_directory.files.iter().any(|handle| self.file(*handle).name == name) {
+            return Err(Error::NameConflict(self.child_path(parent, &name)));
+        }
+        let mut dirs = parent_directory.dirs.iter();
+        if let Some(handle) = dirs.find(|handle| self.dir(**handle).name == name) {
+            return Ok(self.dir(*handle));
+        }
+        let handle = self.alloc_dir();
+        self.dir_entry_mut(parent).unwrap().dirs.push(handle);
+        self.dirs[handle.index] = Some(Directory {
             handle,
             name,
-            parent: parent_directory.handle,
+            parent,
             dirs: [].into(),
             files: [].into(),
-        };
-        parent_directory.dirs.push(handle);
-        self.dirs.push(directory);
-        self.dir_entry(index).ok_or("Invalid directory index")
+            size: 0,
+            metadata: None,
+            quota: None,
+        });
+        self.after_mutation();
+        Ok(self.dir(handle))
     }
 }
 
@@ -260,8 +1288,8 @@ mod unittest {
         assert!(file1.is_ok());
 
         let file1 = file1.unwrap();
-        assert_eq!(file1.view(&fs).name, "file1");
-        assert_eq!(file1.view(&fs).dir.index, root.index);
+        assert_eq!(file1.view(&fs).unwrap().name, "file1");
+        assert_eq!(file1.view(&fs).unwrap().dir.index, root.index);
     }
 
     #[test]
@@ -293,7 +1321,7 @@ mod unittest {
         assert!(dir1.is_ok());
 
         let mut dir1 = dir1.unwrap();
-        let view1 = dir1.view(&fs);
+        let view1 = dir1.view(&fs).unwrap();
         assert_eq!(view1.name, "dir1");
         assert_eq!(view1.parent, root);
 
@@ -301,15 +1329,15 @@ mod unittest {
         assert!(dir2.is_ok());
 
         let mut dir2 = dir2.unwrap();
-        let view2 = dir2.view(&fs);
+        let view2 = dir2.view(&fs).unwrap();
         assert_eq!(view2.name, "dir2");
         assert_eq!(view2.parent, dir1);
-        assert!(dir1.view(&fs).dirs.contains(&dir2));
+        assert!(dir1.view(&fs).unwrap().dirs.contains(&dir2));
 
         let file1 = dir2.new_file("file1".into(), 42, &mut fs);
         assert!(file1.is_ok());
         let file1 = file1.unwrap();
-        assert!(dir2.view(&fs).files.contains(&file1));
+        assert!(dir2.view(&fs).unwrap().files.contains(&file1));
     }
 
     /// A basic test for absolute path
@@ -359,5 +1387,414 @@ mod unittest {
         assert_eq!(dir3.size(&fs), 99);
         assert_eq!(root.size(&fs), 42 + 99);
     }
-}
 
+    /// Cached sizes are updated incrementally and match a full fold
+    #[test]
+    fn cached_size() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let mut dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        assert_eq!(root.size(&fs), 0);
+
+        let _ = dir2.new_file("file1".into(), 10, &mut fs);
+        let _ = dir1.new_file("file2".into(), 20, &mut fs);
+        let _ = root.new_file("file3".into(), 30, &mut fs);
+        // re-listing an existing file must not count it twice
+        let _ = dir2.new_file("file1".into(), 10, &mut fs);
+
+        assert_eq!(dir2.size(&fs), 10);
+        assert_eq!(dir1.size(&fs), 30);
+        assert_eq!(root.size(&fs), 60);
+
+        let folded = |dir: DirectoryHandle| {
+            dir.fold(&fs, 0usize, &|accum, entry| match entry {
+                Entry::File(file) => accum + file.size,
+                Entry::Directory(_) => accum,
+            })
+        };
+        let sizes = fs.dir_sizes();
+        assert_eq!(sizes.len(), 3);
+        for (dir, size) in sizes {
+            assert_eq!(size, folded(dir));
+        }
+    }
+
+    /// Resolve absolute and relative paths with `.`, `..` and repeated separators
+    #[test]
+    fn lookup() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        let file1 = dir1.new_file("file1".into(), 42, &mut fs).unwrap();
+
+        assert_eq!(fs.lookup(root, "/"), Ok(Entry::Directory(root.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(dir2, ""), Ok(Entry::Directory(dir2.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(root, "dir1"), Ok(Entry::Directory(dir1.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(dir2, "/dir1//dir2/"), Ok(Entry::Directory(dir2.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(dir2, "../file1"), Ok(Entry::File(file1.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(dir2, "./../../.."), Ok(Entry::Directory(root.view(&fs).unwrap())));
+        assert_eq!(dir1.lookup("dir2/../file1", &fs), Ok(Entry::File(file1.view(&fs).unwrap())));
+
+        assert_eq!(
+            fs.lookup(root, "/dir1/missing/file1"),
+            Err(LookupError::NotFound("/dir1/missing".into())));
+        assert_eq!(
+            fs.lookup(root, "dir1/file1/dir2"),
+            Err(LookupError::NotADirectory("/dir1/file1".into())));
+        assert_eq!(
+            fs.lookup(root, "dir1/file1/"),
+            Err(LookupError::NotADirectory("/dir1/file1".into())));
+    }
+
+    /// Removal keeps the remaining handles valid and sizes up to date
+    #[test]
+    fn remove() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let mut dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        let file1 = root.new_file("file1".into(), 1, &mut fs).unwrap();
+        let file2 = dir1.new_file("file2".into(), 10, &mut fs).unwrap();
+        let file3 = dir2.new_file("file3".into(), 100, &mut fs).unwrap();
+        assert_eq!(root.size(&fs), 111);
+
+        assert_eq!(fs.remove_file(file2), Ok(10));
+        assert_eq!(fs.remove_file(file2), Err(Error::BadHandle(HandleError::Stale)));
+        assert!(dir1.view(&fs).unwrap().files.is_empty());
+        assert_eq!(dir1.size(&fs), 100);
+        assert_eq!(root.size(&fs), 101);
+
+        assert_eq!(fs.remove_directory(dir1), Ok(100));
+        assert_eq!(fs.remove_directory(dir2), Err(Error::BadHandle(HandleError::Stale)));
+        assert_eq!(fs.remove_directory(root), Err(Error::RootDirectory));
+        assert!(fs.files[file3.index].is_none());
+        assert!(root.view(&fs).unwrap().dirs.is_empty());
+        assert_eq!(root.size(&fs), 1);
+        assert_eq!(file1.abspath(&fs), "/file1");
+        assert_eq!(fs.dir_sizes(), [(root, 1)]);
+    }
+
+    /// Rename and move entries between directories
+    #[test]
+    fn rename_and_move() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        let dir3 = root.new_directory("dir3".into(), &mut fs).unwrap();
+        let file1 = dir1.new_file("file1".into(), 42, &mut fs).unwrap();
+
+        assert_eq!(file1.rename("dir2".into(), &mut fs), Err(Error::NameConflict("/dir1/dir2".into())));
+        assert_eq!(file1.rename("file2".into(), &mut fs), Ok(()));
+        assert_eq!(file1.abspath(&fs), "/dir1/file2");
+        assert_eq!(root.rename("root".into(), &mut fs), Err(Error::RootDirectory));
+
+        assert_eq!(file1.move_to(dir3, &mut fs), Ok(()));
+        assert_eq!(file1.abspath(&fs), "/dir3/file2");
+        assert_eq!((dir1.size(&fs), dir3.size(&fs), root.size(&fs)), (0, 42, 42));
+
+        assert_eq!(dir1.move_to(dir2, &mut fs), Err(Error::MoveIntoSubtree("/dir1/".into())));
+        assert_eq!(dir3.move_to(dir2, &mut fs), Ok(()));
+        assert_eq!(dir3.rename("dir4".into(), &mut fs), Ok(()));
+        assert_eq!(file1.abspath(&fs), "/dir1/dir2/dir4/file2");
+        assert_eq!((dir1.size(&fs), dir2.size(&fs), root.size(&fs)), (42, 42, 42));
+        assert_eq!(root.view(&fs).unwrap().dirs, [dir1]);
+
+        assert_eq!(fs.move_file(file1, root, "dir1".into()), Err(Error::NameConflict("/dir1".into())));
+        assert_eq!(fs.move_file(file1, root, "file3".into()), Ok(()));
+        assert_eq!(file1.abspath(&fs), "/file3");
+        assert_eq!((dir1.size(&fs), dir2.size(&fs), root.size(&fs)), (0, 0, 42));
+    }
+
+    /// Handles are rejected after removal and by other filesystems
+    #[test]
+    fn stale_and_foreign_handle() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let file1 = root.new_file("file1".into(), 42, &mut fs).unwrap();
+
+        let other = BasicFileSystem::new();
+        assert_ne!(fs.id(), other.id());
+        assert_eq!(root.view(&other), Err(HandleError::ForeignFileSystem));
+        assert_eq!(dir1.view(&other), Err(HandleError::ForeignFileSystem));
+        assert_eq!(file1.view(&other), Err(HandleError::ForeignFileSystem));
+
+        fs.remove_directory(dir1).unwrap();
+        fs.remove_file(file1).unwrap();
+        assert_eq!(dir1.view(&fs), Err(HandleError::Stale));
+        assert_eq!(file1.view(&fs), Err(HandleError::Stale));
+
+        // freed slots are reused with a newer generation
+        let dir2 = root.new_directory("dir2".into(), &mut fs).unwrap();
+        let file2 = root.new_file("file2".into(), 7, &mut fs).unwrap();
+        assert_eq!((dir2.index, file2.index), (dir1.index, file1.index));
+        assert_eq!(dir1.view(&fs), Err(HandleError::Stale));
+        assert_eq!(file1.view(&fs), Err(HandleError::Stale));
+        assert_eq!(fs.remove_file(file1), Err(Error::BadHandle(HandleError::Stale)));
+        assert_eq!(file2.view(&fs).unwrap().name, "file2");
+    }
+
+    /// Files and directories share a single namespace per directory
+    #[test]
+    fn name_conflict() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let _ = dir1.new_file("file1".into(), 42, &mut fs).unwrap();
+
+        assert_eq!(
+            root.new_file("dir1".into(), 42, &mut fs),
+            Err(Error::NameConflict("/dir1".into())));
+        assert_eq!(
+            dir1.new_directory("file1".into(), &mut fs),
+            Err(Error::NameConflict("/dir1/file1".into())));
+        assert_eq!(root.view(&fs).unwrap().files, []);
+        assert_eq!(dir1.view(&fs).unwrap().dirs, []);
+    }
+
+    /// Re-listed files with a different size follow the [ConflictPolicy]
+    #[test]
+    fn size_conflict() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let file1 = root.new_file("file1".into(), 42, &mut fs).unwrap();
+        assert_eq!(root.new_file("file1".into(), 7, &mut fs), Ok(file1));
+        assert_eq!(file1.size(&fs), 42);
+
+        let mut fs = BasicFileSystem::with_conflict_policy(ConflictPolicy::KeepLast);
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let file1 = dir1.new_file("file1".into(), 42, &mut fs).unwrap();
+        assert_eq!(dir1.new_file("file1".into(), 7, &mut fs), Ok(file1));
+        assert_eq!((file1.size(&fs), dir1.size(&fs), root.size(&fs)), (7, 7, 7));
+
+        let mut fs = BasicFileSystem::with_conflict_policy(ConflictPolicy::Reject);
+        let mut root = fs.root();
+        let file1 = root.new_file("file1".into(), 42, &mut fs).unwrap();
+        assert_eq!(root.new_file("file1".into(), 42, &mut fs), Ok(file1));
+        assert_eq!(
+            root.new_file("file1".into(), 7, &mut fs),
+            Err(Error::SizeConflict { path: "/file1".into(), listed: 42, size: 7 }));
+        assert_eq!(root.size(&fs), 42);
+    }
+
+    /// Hard links share an inode, which directories holding several of its
+    /// names count once
+    #[test]
+    fn hard_links() {
+        let mut fs = BasicFileSystem::with_conflict_policy(ConflictPolicy::KeepLast);
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let mut dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        let mut dir3 = root.new_directory("dir3".into(), &mut fs).unwrap();
+        let file1 = dir2.new_file("file1".into(), 100, &mut fs).unwrap();
+        let link1 = dir1.new_hard_link("link1".into(), file1, &mut fs).unwrap();
+        let link2 = dir3.new_hard_link("link2".into(), link1, &mut fs).unwrap();
+        assert_eq!(dir1.new_hard_link("link1".into(), file1, &mut fs), Ok(link1));
+        assert_eq!(
+            dir1.new_hard_link("dir2".into(), file1, &mut fs),
+            Err(Error::NameConflict("/dir1/dir2".into())));
+
+        assert_eq!(file1.links(&fs), [file1, link1, link2]);
+        assert_eq!(link2.view(&fs).unwrap().inode, file1.view(&fs).unwrap().inode);
+        assert_eq!((dir2.size(&fs), dir1.size(&fs), dir3.size(&fs), root.size(&fs)), (100, 100, 100, 100));
+
+        // a new size applies to every name
+        let _ = dir2.new_file("file1".into(), 10, &mut fs);
+        assert_eq!((link2.size(&fs), dir1.size(&fs), dir3.size(&fs), root.size(&fs)), (10, 10, 10, 10));
+
+        assert_eq!(fs.remove_file(link1), Ok(0));
+        assert_eq!(file1.links(&fs), [file1, link2]);
+        assert_eq!(dir3.move_to(dir2, &mut fs), Ok(()));
+        assert_eq!((dir2.size(&fs), dir3.size(&fs), root.size(&fs)), (10, 10, 10));
+        assert_eq!(fs.remove_directory(dir3), Ok(0));
+        assert_eq!(file1.links(&fs), [file1]);
+        assert_eq!(fs.remove_directory(dir1), Ok(10));
+        assert_eq!(root.size(&fs), 0);
+    }
+
+    /// Symbolic links are followed by lookups, except in last position by
+    /// [BasicFileSystem::lookup_link]
+    #[test]
+    fn symlinks() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dir1 = root.new_directory("dir1".into(), &mut fs).unwrap();
+        let dir2 = dir1.new_directory("dir2".into(), &mut fs).unwrap();
+        let file1 = dir1.new_file("file1".into(), 42, &mut fs).unwrap();
+        let to_dir = root.new_symlink("to_dir".into(), "dir1/dir2/..".into(), &mut fs).unwrap();
+        let to_file = dir1.new_symlink("to_file".into(), "/to_dir/file1".into(), &mut fs).unwrap();
+        let _ = root.new_symlink("loop".into(), "loop/x".into(), &mut fs).unwrap();
+        let _ = root.new_symlink("dangling".into(), "dir1/missing".into(), &mut fs).unwrap();
+        assert_eq!(root.new_symlink("to_dir".into(), "dir1/dir2/..".into(), &mut fs), Ok(to_dir));
+        assert_eq!(root.new_symlink("to_dir".into(), "dir1".into(), &mut fs), Err(Error::NameConflict("/to_dir".into())));
+        assert_eq!(root.new_file("to_dir".into(), 12, &mut fs), Err(Error::NameConflict("/to_dir".into())));
+
+        assert_eq!(to_file.size(&fs), 13);
+        assert_eq!(root.size(&fs), 42 + 12 + 13 + 6 + 12);
+        assert_eq!(fs.lookup(root, "to_dir"), Ok(Entry::Directory(dir1.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(root, "/to_dir/dir2/"), Ok(Entry::Directory(dir2.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(dir2, "../to_file"), Ok(Entry::File(file1.view(&fs).unwrap())));
+        assert_eq!(fs.lookup_link(dir2, "../to_file"), Ok(Entry::File(to_file.view(&fs).unwrap())));
+        assert_eq!(fs.lookup_link(root, "to_dir/"), Ok(Entry::Directory(dir1.view(&fs).unwrap())));
+        assert_eq!(fs.lookup(root, "to_dir/to_file/x"), Err(LookupError::NotADirectory("/dir1/file1".into())));
+        assert_eq!(fs.lookup(root, "dangling"), Err(LookupError::NotFound("/dir1/missing".into())));
+        assert_eq!(fs.lookup(root, "/loop"), Err(LookupError::SymlinkLoop("/loop".into())));
+        assert!(fs.lookup_link(root, "/loop").is_ok());
+    }
+
+    /// Snapshots share their arena until written to and evolve independently
+    #[test]
+    fn snapshots() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut dirs = vec![];
+        for i in 0..100 {
+            let mut dir = root.new_directory(format!("dir{}", i), &mut fs).unwrap();
+            dir.new_file("file".into(), i, &mut fs).unwrap();
+            dirs.push(dir);
+        }
+        assert_eq!(root.size(&fs), 4950);
+
+        // fork twice and remove a different directory in each fork
+        let mut fork1 = fs.snapshot();
+        let mut fork2 = fs.snapshot();
+        assert_eq!(fs.dirs.shared_chunks(&fork1.dirs), 2);
+        fork1.remove_directory(dirs[99]).unwrap();
+        fork2.remove_directory(dirs[10]).unwrap();
+        fork2.remove_directory(dirs[20]).unwrap();
+        assert_eq!((root.size(&fs), root.size(&fork1), root.size(&fork2)), (4950, 4851, 4920));
+        // every mutation writes to the root, in the first chunk of directories
+        assert_eq!(fs.dirs.shared_chunks(&fork1.dirs), 0);
+        assert_eq!(fs.files.shared_chunks(&fork1.files), 1);
+        assert_eq!(fs.files.shared_chunks(&fork2.files), 1);
+        assert!(dirs[99].view(&fs).is_ok() && dirs[99].view(&fork1).is_err());
+
+        // forks evolve independently, slots freed in one are reused in it only
+        let file = root.new_file("new".into(), 1000, &mut fork2).unwrap();
+        assert_eq!(file.index, 20);
+        assert_eq!(root.size(&fork2), 5920);
+        assert!(fork2.lookup(root, "/new").is_ok() && fs.lookup(root, "/new").is_err());
+        assert_eq!(file.view(&fs), Err(HandleError::Stale));
+
+        // entries created after the fork in the same slot get different handles
+        let mut fork3 = fs.snapshot();
+        let mine = root.new_file("x".into(), 1, &mut fs).unwrap();
+        let theirs = root.new_file("x".into(), 1, &mut fork3).unwrap();
+        assert_eq!(mine.index, theirs.index);
+        assert_ne!(mine, theirs);
+        assert_eq!((mine.view(&fork3), theirs.view(&fs)), (Err(HandleError::Stale), Err(HandleError::Stale)));
+        fs.remove_file(mine).unwrap();
+        assert!(fork1.fsck().is_ok() && fork2.fsck().is_ok() && fs.fsck().is_ok());
+        drop(fork1);
+        assert_eq!(root.size(&fs), 4950);
+    }
+
+    /// Growth above a quota or the capacity is rejected, moves included
+    #[test]
+    fn quotas() {
+        let mut fs = BasicFileSystem::with_conflict_policy(ConflictPolicy::KeepLast);
+        let mut root = fs.root();
+        let mut home = root.new_directory("home".into(), &mut fs).unwrap();
+        let mut user = home.new_directory("user".into(), &mut fs).unwrap();
+        let mut tmp = root.new_directory("tmp".into(), &mut fs).unwrap();
+        fs.set_capacity(Some(1000));
+        home.set_quota(Some(500), &mut fs).unwrap();
+        user.set_quota(Some(300), &mut fs).unwrap();
+
+        let file = user.new_file("a".into(), 300, &mut fs).unwrap();
+        assert_eq!(
+            user.new_file("b".into(), 1, &mut fs),
+            Err(Error::QuotaExceeded { path: "/home/user/".into(), quota: 300, size: 301 })
+        );
+        assert_eq!(user.new_file("a".into(), 301, &mut fs), Err(Error::QuotaExceeded {
+            path: "/home/user/".into(),
+            quota: 300,
+            size: 301,
+        }));
+        user.new_file("a".into(), 100, &mut fs).unwrap();
+        home.new_file("c".into(), 400, &mut fs).unwrap();
+        assert!(matches!(home.new_symlink("d".into(), "c".into(), &mut fs), Err(Error::QuotaExceeded { .. })));
+        tmp.new_file("e".into(), 400, &mut fs).unwrap();
+        assert_eq!(tmp.new_file("f".into(), 101, &mut fs), Err(Error::DiskFull { capacity: 1000, size: 1001 }));
+        assert!(fs.lookup(root, "/tmp/f").is_err());
+
+        // a hard link only grows the directories that do not count its inode
+        user.new_hard_link("a2".into(), file, &mut fs).unwrap();
+        tmp.new_hard_link("a3".into(), file, &mut fs).unwrap();
+        home.set_quota(Some(100), &mut fs).unwrap();
+        home.new_hard_link("a4".into(), file, &mut fs).unwrap();
+
+        // moves grow the directories that the file or subtree enters only
+        let e = fs.lookup(root, "/tmp/e").map(|entry| match entry {
+            Entry::File(file) => file.handle,
+            Entry::Directory(_) => unreachable!(),
+        }).unwrap();
+        assert!(matches!(fs.move_file(e, user, "e".into()), Err(Error::QuotaExceeded { .. })));
+        fs.move_file(e, root, "e".into()).unwrap();
+        fs.move_directory(tmp, user, "tmp".into()).unwrap();
+        fs.move_directory(tmp, home, "tmp".into()).unwrap();
+        assert_eq!(tmp.size(&fs), 100);
+        let mut big = root.new_directory("big".into(), &mut fs).unwrap();
+        big.new_file("g".into(), 1, &mut fs).unwrap();
+        assert_eq!(
+            fs.move_directory(big, user, "big".into()),
+            Err(Error::QuotaExceeded { path: "/home/".into(), quota: 100, size: 501 })
+        );
+        assert_eq!(
+            fs.quota_usage(),
+            [QuotaUsage { dir: home, quota: 100, used: 500 }, QuotaUsage { dir: user, quota: 300, used: 100 }]
+        );
+        assert_eq!(fs.quota_usage()[1].available(), 200);
+        assert_eq!(root.size(&fs), 901);
+        assert_eq!(fs.capacity(), Some(1000));
+
+        fs.remove_directory(home).unwrap();
+        assert!(fs.quota_usage().is_empty());
+        fs.set_capacity(None);
+        root.new_file("h".into(), 10_000, &mut fs).unwrap();
+    }
+
+    /// Sizes use the whole range of `usize`, growing past it is an error
+    #[test]
+    fn size_overflow() {
+        let mut fs = BasicFileSystem::with_conflict_policy(ConflictPolicy::KeepLast);
+        let mut root = fs.root();
+        let mut d = root.new_directory("d".into(), &mut fs).unwrap();
+        let huge = d.new_file("a".into(), usize::MAX - 1, &mut fs).unwrap();
+        assert_eq!(root.size(&fs), usize::MAX - 1);
+        root.new_file("b".into(), 1, &mut fs).unwrap();
+        assert_eq!(root.new_file("c".into(), 1, &mut fs), Err(Error::SizeOverflow("/".into())));
+        assert_eq!(d.new_file("a".into(), usize::MAX, &mut fs), Err(Error::SizeOverflow("/".into())));
+        assert_eq!(root.new_symlink("c".into(), "b".into(), &mut fs), Err(Error::SizeOverflow("/".into())));
+        assert_eq!(Error::SizeOverflow("/".into()).to_string(), "/: size too large");
+
+        // moving and shrinking a file larger than `isize::MAX`
+        fs.move_file(huge, root, "a".into()).unwrap();
+        assert_eq!((root.size(&fs), d.size(&fs)), (usize::MAX, 0));
+        root.new_file("a".into(), isize::MAX as usize + 1, &mut fs).unwrap();
+        assert_eq!(root.size(&fs), isize::MAX as usize + 2);
+        assert_eq!(fs.remove_file(huge), Ok(isize::MAX as usize + 1));
+        assert_eq!(root.size(&fs), 1);
+    }
+
+    /// Checking a filesystem whose names add up to more than `usize::MAX`
+    #[test]
+    fn size_overflow_hard_links() {
+        let mut fs = BasicFileSystem::new();
+        let mut root = fs.root();
+        let mut d = root.new_directory("d".into(), &mut fs).unwrap();
+        let half = d.new_file("a".into(), usize::MAX / 2 + 1, &mut fs).unwrap();
+        d.new_hard_link("b".into(), half, &mut fs).unwrap();
+        root.new_hard_link("c".into(), half, &mut fs).unwrap();
+        assert_eq!((root.size(&fs), d.size(&fs)), (usize::MAX / 2 + 1, usize::MAX / 2 + 1));
+        assert!(fs.fsck().is_ok());
+
+        fs.dirs[d.index].as_mut().unwrap().size = 0;
+        assert_eq!(fs.repair().to_string(), format!("/d/: cached size 0 instead of {}\n", usize::MAX / 2 + 1));
+        assert!(fs.fsck().is_ok());
+    }
+}
@@ -0,0 +1,776 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::fs::{BasicFileSystem, Directory, DirectoryHandle, Entry, File, FileHandle, FileKind};
+use crate::query::Kind;
+
+/// Name of the directory below the root that receives detached entries
+pub const LOST_AND_FOUND: &str = "lost+found";
+
+/// A broken invariant of a [BasicFileSystem]. Paths are followed through
+/// `parent` links; a path that does not lead to the root starts with `?`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Violation {
+    /// Slot 0 does not hold a directory that is its own parent
+    BadRoot,
+    /// The handle stored in an entry does not match its slot or filesystem,
+    /// or has a generation that has not been handed out yet
+    BadHandle { path: String },
+    /// A directory lists a handle that refers to no live entry
+    DanglingEntry { path: String },
+    /// An entry is listed by a directory other than its parent, or twice
+    WrongListing { path: String },
+    /// An entry is not listed by the directory it points to, or points to no
+    /// live directory
+    Unlisted { path: String },
+    /// A directory other than the root is its own parent
+    SelfParent { path: String },
+    /// A directory is its own ancestor
+    Cycle { path: String },
+    /// Two entries of a directory have the same name
+    DuplicateName { path: String },
+    /// An entry other than the root has an empty name or one containing `/`
+    BadName { path: String },
+    /// The cached size of a directory is not the total of its contents
+    WrongSize { path: String, cached: usize, actual: usize },
+    /// A slot on the free list is out of range or holds a live entry
+    BadFreeSlot { kind: Kind, index: usize },
+    /// A hard link differs in size or kind from the first name of its inode
+    LinkMismatch { path: String },
+    /// The recorded names of an inode are not the files that share it
+    WrongLinks { inode: usize },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::BadRoot => write!(f, "missing or malformed root directory"),
+            Violation::BadHandle { path } => write!(f, "{}: handle does not match its slot", path),
+            Violation::DanglingEntry { path } => write!(f, "{}: lists a removed or unknown entry", path),
+            Violation::WrongListing { path } => write!(f, "{}: listed by the wrong directory", path),
+            Violation::Unlisted { path } => write!(f, "{}: not listed by its directory", path),
+            Violation::SelfParent { path } => write!(f, "{}: is its own parent", path),
+            Violation::Cycle { path } => write!(f, "{}: is its own ancestor", path),
+            Violation::DuplicateName { path } => write!(f, "{}: name already exists", path),
+            Violation::BadName { path } => write!(f, "{}: invalid name", path),
+            Violation::WrongSize { path, cached, actual } => {
+                write!(f, "{}: cached size {} instead of {}", path, cached, actual)
+            }
+            Violation::BadFreeSlot { kind, index } => write!(f, "free {:?} slot {} is in use", kind, index),
+            Violation::LinkMismatch { path } => write!(f, "{}: differs from the other links to its inode", path),
+            Violation::WrongLinks { inode } => write!(f, "inode {}: hard links are not recorded", inode),
+        }
+    }
+}
+
+/// Outcome of [check] or [repair]
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Report {
+    pub violations: Vec<Violation>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for violation in &self.violations {
+            writeln!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Live directory behind `handle`, without panicking on a broken link
+fn dir(fs: &BasicFileSystem, handle: DirectoryHandle) -> Option<&Directory> {
+    let dir = fs.dirs.get(handle.index)?.as_ref()?;
+    (dir.handle == handle && handle.fs_id == fs.id()).then_some(dir)
+}
+
+fn file(fs: &BasicFileSystem, handle: FileHandle) -> Option<&File> {
+    let file = fs.files.get(handle.index)?.as_ref()?;
+    (file.handle == handle && handle.fs_id == fs.id()).then_some(file)
+}
+
+/// Whether `name` cannot be the name of an entry other than the root
+fn bad_name(name: &str) -> bool {
+    name.is_empty() || name.contains('/')
+}
+
+/// Best effort path of the directory in slot `index`
+fn dir_path(fs: &BasicFileSystem, index: usize) -> String {
+    let mut names = vec![];
+    let mut visited = HashSet::new();
+    let mut current = index;
+    let mut attached = false;
+    while let Some(Some(view)) = fs.dirs.get(current) {
+        if current == 0 && view.is_root() {
+            attached = true;
+            break;
+        }
+        if !visited.insert(current) {
+            break;
+        }
+        names.push(view.name.as_str());
+        current = view.parent.index;
+    }
+    let mut path = String::from(if attached { "/" } else { "?/" });
+    for name in names.iter().rev() {
+        path.push_str(name);
+        path.push('/');
+    }
+    path
+}
+
+fn file_path(fs: &BasicFileSystem, index: usize) -> String {
+    let view = fs.files[index].as_ref().unwrap();
+    let mut path = match fs.dirs.get(view.dir.index) {
+        Some(Some(_)) => dir_path(fs, view.dir.index),
+        _ => "?/".into(),
+    };
+    path.push_str(&view.name);
+    path
+}
+
+/// Slots of the directories that are their own ancestor, found by following
+/// each `parent` chain once
+fn cycles(fs: &BasicFileSystem) -> Vec<Vec<usize>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unknown,
+        Visiting,
+        Done,
+    }
+    let mut state = vec![State::Unknown; fs.dirs.len()];
+    let mut cycles = vec![];
+    for start in 0..fs.dirs.len() {
+        let mut chain = vec![];
+        let mut current = start;
+        while let Some(view) = fs.dirs[current].as_ref() {
+            if state[current] == State::Done || (current == 0 && view.is_root()) {
+                break;
+            }
+            if state[current] == State::Visiting {
+                let position = chain.iter().position(|index| *index == current).unwrap();
+                cycles.push(chain[position..].to_vec());
+                break;
+            }
+            state[current] = State::Visiting;
+            chain.push(current);
+            match dir(fs, view.parent) {
+                Some(parent) if parent.handle != view.handle => current = view.parent.index,
+                _ => break,
+            }
+        }
+        for index in chain {
+            state[index] = State::Done;
+        }
+    }
+    cycles
+}
+
+/// Bytes that a plain sum of the files below each directory counts more than
+/// once, because several names of the same inode are below it. `parents` maps
+/// each directory slot but the root's to the slot of its parent, `inodes`
+/// gives the size of each inode with more than one name and the slots of the
+/// directories holding them.
+///
+/// Plain sums count every name, so they can exceed `usize::MAX` on a valid
+/// filesystem and are kept as `u128`.
+fn overcount(parents: &HashMap<usize, usize>, inodes: Vec<(usize, Vec<usize>)>) -> HashMap<usize, u128> {
+    let mut overcount: HashMap<usize, u128> = HashMap::new();
+    for (size, dirs) in inodes {
+        let mut names: HashMap<usize, usize> = HashMap::new();
+        for dir in dirs {
+            let mut current = Some(dir);
+            while let Some(index) = current {
+                *names.entry(index).or_default() += 1;
+                current = parents.get(&index).copied();
+            }
+        }
+        for (index, count) in names {
+            *overcount.entry(index).or_default() += (count - 1) as u128 * size as u128;
+        }
+    }
+    overcount
+}
+
+/// Size counting every inode once, from the `plain` sum of a directory and
+/// its `overcount`. Saturates when the sizes of an arena do not add up.
+fn counted_once(plain: u128, overcount: Option<&u128>) -> u128 {
+    plain.saturating_sub(overcount.copied().unwrap_or(0))
+}
+
+/// Files sharing an inode, in slot order, for the inodes with several names
+fn inodes<'a>(files: impl Iterator<Item = &'a File>) -> HashMap<usize, Vec<&'a File>> {
+    let mut inodes: HashMap<usize, Vec<&File>> = HashMap::new();
+    for file in files {
+        inodes.entry(file.inode).or_default().push(file);
+    }
+    inodes.retain(|_, links| links.len() > 1);
+    inodes.values_mut().for_each(|links| links.sort_by_key(|link| link.handle.index));
+    inodes
+}
+
+/// Check every invariant of `fs` and report all violations
+pub fn check(fs: &BasicFileSystem) -> Report {
+    let mut violations = vec![];
+    match fs.dirs.first() {
+        Some(Some(root)) if root.handle == fs.root() && root.is_root() => (),
+        _ => violations.push(Violation::BadRoot),
+    }
+
+    let (free_dirs, free_files) = fs.free_slots();
+    for (kind, free, live) in [
+        (Kind::Directory, free_dirs, fs.dirs.iter().map(Option::is_some).collect::<Vec<_>>()),
+        (Kind::File, free_files, fs.files.iter().map(Option::is_some).collect()),
+    ] {
+        for index in free {
+            if live.get(*index).is_none_or(|live| *live) {
+                violations.push(Violation::BadFreeSlot { kind, index: *index });
+            }
+        }
+    }
+
+    let generation = fs.generation();
+    let mut dir_listers: HashMap<usize, Vec<DirectoryHandle>> = HashMap::new();
+    let mut file_listers: HashMap<usize, Vec<DirectoryHandle>> = HashMap::new();
+    for (index, view) in fs.dirs.iter().enumerate() {
+        let Some(view) = view else { continue };
+        let handle = view.handle;
+        if handle.index != index || handle.fs_id != fs.id() || (index != 0 && handle.generation >= generation) {
+            violations.push(Violation::BadHandle { path: dir_path(fs, index) });
+        }
+        if index != 0 && bad_name(&view.name) {
+            violations.push(Violation::BadName { path: dir_path(fs, index) });
+        }
+        let mut names = HashSet::new();
+        for child in &view.dirs {
+            match dir(fs, *child) {
+                Some(child) => {
+                    dir_listers.entry(child.handle.index).or_default().push(view.handle);
+                    if !names.insert(child.name.as_str()) {
+                        violations.push(Violation::DuplicateName { path: dir_path(fs, child.handle.index) });
+                    }
+                }
+                None => violations.push(Violation::DanglingEntry { path: dir_path(fs, index) }),
+            }
+        }
+        for child in &view.files {
+            match file(fs, *child) {
+                Some(child) => {
+                    file_listers.entry(child.handle.index).or_default().push(view.handle);
+                    if !names.insert(child.name.as_str()) {
+                        violations.push(Violation::DuplicateName { path: file_path(fs, child.handle.index) });
+                    }
+                }
+                None => violations.push(Violation::DanglingEntry { path: dir_path(fs, index) }),
+            }
+        }
+    }
+    for (index, view) in fs.files.iter().enumerate() {
+        let Some(view) = view else { continue };
+        let handle = view.handle;
+        if handle.index != index || handle.fs_id != fs.id() || handle.generation >= generation {
+            violations.push(Violation::BadHandle { path: file_path(fs, index) });
+        }
+        if bad_name(&view.name) {
+            violations.push(Violation::BadName { path: file_path(fs, index) });
+        }
+        let listers = file_listers.remove(&index).unwrap_or_default();
+        if !listers.contains(&view.dir) {
+            violations.push(Violation::Unlisted { path: file_path(fs, index) });
+        }
+        if listers.iter().any(|lister| *lister != view.dir) || listers.len() > 1 {
+            violations.push(Violation::WrongListing { path: file_path(fs, index) });
+        }
+    }
+    for (index, view) in fs.dirs.iter().enumerate().skip(1) {
+        let Some(view) = view else { continue };
+        let listers = dir_listers.remove(&index).unwrap_or_default();
+        if view.is_root() {
+            violations.push(Violation::SelfParent { path: dir_path(fs, index) });
+        } else if !listers.contains(&view.parent) {
+            violations.push(Violation::Unlisted { path: dir_path(fs, index) });
+        }
+        if listers.iter().any(|lister| *lister != view.parent) || listers.len() > 1 {
+            violations.push(Violation::WrongListing { path: dir_path(fs, index) });
+        }
+    }
+    if dir_listers.contains_key(&0) {
+        violations.push(Violation::WrongListing { path: "/".into() });
+    }
+    for cycle in cycles(fs) {
+        violations.extend(cycle.into_iter().map(|index| Violation::Cycle { path: dir_path(fs, index) }));
+    }
+
+    // hard links
+    let linked = inodes(fs.files.iter().flatten());
+    for links in linked.values() {
+        for link in &links[1..] {
+            if link.size != links[0].size || link.kind != links[0].kind {
+                violations.push(Violation::LinkMismatch { path: file_path(fs, link.handle.index) });
+            }
+        }
+    }
+    let expected: HashMap<usize, HashSet<FileHandle>> = linked
+        .iter()
+        .map(|(inode, links)| (*inode, links.iter().map(|link| link.handle).collect()))
+        .collect();
+    let recorded: HashMap<usize, HashSet<FileHandle>> = fs
+        .hard_links()
+        .iter()
+        .map(|(inode, links)| (*inode, links.iter().copied().collect()))
+        .collect();
+    let mut wrong: Vec<usize> = recorded
+        .keys()
+        .chain(expected.keys())
+        .filter(|inode| recorded.get(inode) != expected.get(inode))
+        .copied()
+        .collect();
+    wrong.sort();
+    wrong.dedup();
+    violations.extend(wrong.into_iter().map(|inode| Violation::WrongLinks { inode }));
+
+    // cached sizes, over the directories reachable through listings, hard
+    // links being counted once
+    if let Some(Some(root)) = fs.dirs.first() {
+        let mut visited = HashSet::from([root.handle.index]);
+        let mut order = vec![root];
+        let mut parents = HashMap::new();
+        let mut holders = HashMap::new();
+        let mut position = 0;
+        while let Some(view) = order.get(position) {
+            let index = view.handle.index;
+            for child in view.files.iter().filter_map(|child| file(fs, *child)) {
+                holders.entry(child.handle.index).or_insert(index);
+            }
+            for child in view.dirs.iter().filter_map(|child| dir(fs, *child)) {
+                if visited.insert(child.handle.index) {
+                    parents.insert(child.handle.index, index);
+                    order.push(child);
+                }
+            }
+            position += 1;
+        }
+        let reachable = inodes(holders.keys().map(|index| fs.files[*index].as_ref().unwrap()));
+        let links = reachable
+            .into_values()
+            .map(|links| (links[0].size, links.iter().map(|link| holders[&link.handle.index]).collect()))
+            .collect();
+        let overcount = overcount(&parents, links);
+        let mut plain: HashMap<usize, u128> = HashMap::new();
+        for view in order.iter().rev() {
+            let files: u128 = view.files.iter().filter_map(|child| file(fs, *child)).map(|file| file.size as u128).sum();
+            let dirs: u128 = view.dirs.iter().filter_map(|child| plain.get(&child.index)).sum();
+            let index = view.handle.index;
+            plain.insert(index, files + dirs);
+            let size = counted_once(files + dirs, overcount.get(&index));
+            if view.size as u128 != size {
+                let path = dir_path(fs, index);
+                let actual = usize::try_from(size).unwrap_or(usize::MAX);
+                violations.push(Violation::WrongSize { path, cached: view.size, actual });
+            }
+        }
+    }
+    Report { violations }
+}
+
+/// Directory below the root that receives detached entries, created on demand
+fn lost_and_found(fs: &mut BasicFileSystem) -> DirectoryHandle {
+    let root = fs.root();
+    let mut children = fs.dir(root).dirs.iter();
+    if let Some(handle) = children.find(|child| fs.dir(**child).name == LOST_AND_FOUND).copied() {
+        return handle;
+    }
+    let handle = fs.alloc_dir();
+    fs.dirs[handle.index] = Some(Directory {
+        handle,
+        name: LOST_AND_FOUND.into(),
+        parent: root,
+        dirs: vec![],
+        files: vec![],
+        size: 0,
+        metadata: None,
+        quota: None,
+    });
+    fs.dirs[0].as_mut().unwrap().dirs.push(handle);
+    handle
+}
+
+/// Check `fs` and fix every violation, reporting the violations found.
+///
+/// Parent links are trusted over listings: an entry listed by the wrong
+/// directory is moved back to its parent. Entries whose parent is gone, and
+/// directories that are their own ancestor, are moved to `/lost+found/`.
+/// Slashes in names are replaced by `_` and an empty name becomes the slot
+/// index, duplicate names get the slot index as a suffix, cached sizes are
+/// recomputed and removed handles are dropped from listings.
+pub fn repair(fs: &mut BasicFileSystem) -> Report {
+    let report = check(fs);
+    if report.is_ok() {
+        return report;
+    }
+    let root = fs.root();
+    match fs.dirs.first_mut() {
+        Some(Some(view)) => {
+            view.handle = root;
+            view.parent = root;
+        }
+        slot => {
+            let view = Directory {
+                handle: root,
+                name: "/".into(),
+                parent: root,
+                dirs: vec![],
+                files: vec![],
+                size: 0,
+                metadata: None,
+                quota: None,
+            };
+            match slot {
+                Some(slot) => *slot = Some(view),
+                None => fs.dirs.push(Some(view)),
+            }
+        }
+    }
+    let fs_id = fs.id();
+    for (index, view) in fs.dirs.iter_mut().enumerate() {
+        if let Some(view) = view {
+            view.handle.index = index;
+            view.handle.fs_id = fs_id;
+        }
+    }
+    for (index, view) in fs.files.iter_mut().enumerate() {
+        if let Some(view) = view {
+            view.handle.index = index;
+            view.handle.fs_id = fs_id;
+        }
+    }
+    fs.rebuild_free_slots();
+
+    // adopt entries whose parent is gone by their first lister, then keep each
+    // entry only in the listing of its parent
+    for index in 0..fs.dirs.len() {
+        let Some(view) = fs.dirs[index].as_ref() else { continue };
+        let (handle, dirs, files) = (view.handle, view.dirs.clone(), view.files.clone());
+        for child in dirs {
+            let orphan = dir(fs, child).is_some_and(|child| child.is_root() || dir(fs, child.parent).is_none());
+            if child.index != 0 && orphan {
+                fs.dirs[child.index].as_mut().unwrap().parent = handle;
+            }
+        }
+        for child in files {
+            if file(fs, child).is_some_and(|child| dir(fs, child.dir).is_none()) {
+                fs.files[child.index].as_mut().unwrap().dir = handle;
+            }
+        }
+    }
+    for index in 0..fs.dirs.len() {
+        let Some(view) = fs.dirs[index].as_ref() else { continue };
+        let handle = view.handle;
+        let mut seen = HashSet::new();
+        let dirs: Vec<DirectoryHandle> = view
+            .dirs
+            .iter()
+            .copied()
+            .filter(|child| child.index != 0 && dir(fs, *child).is_some_and(|child| child.parent == handle))
+            .filter(|child| seen.insert(child.index))
+            .collect();
+        let files: Vec<FileHandle> = view
+            .files
+            .iter()
+            .copied()
+            .filter(|child| file(fs, *child).is_some_and(|child| child.dir == handle))
+            .filter(|child| seen.insert(usize::MAX - child.index))
+            .collect();
+        let view = fs.dirs[index].as_mut().unwrap();
+        view.dirs = dirs;
+        view.files = files;
+    }
+
+    // attach unlisted entries to their parent, or to lost+found
+    let mut detached_dirs = vec![];
+    for index in 1..fs.dirs.len() {
+        let Some(view) = fs.dirs[index].as_ref() else { continue };
+        let (handle, parent) = (view.handle, view.parent);
+        if parent == handle || dir(fs, parent).is_none() {
+            detached_dirs.push(handle);
+        } else if !fs.dir(parent).dirs.contains(&handle) {
+            fs.dirs[parent.index].as_mut().unwrap().dirs.push(handle);
+        }
+    }
+    for cycle in cycles(fs) {
+        let handle = fs.dirs[cycle[0]].as_ref().unwrap().handle;
+        let parent = fs.dir(handle).parent;
+        fs.dirs[parent.index].as_mut().unwrap().dirs.retain(|child| *child != handle);
+        detached_dirs.push(handle);
+    }
+    let mut detached_files = vec![];
+    for index in 0..fs.files.len() {
+        let Some(view) = fs.files[index].as_ref() else { continue };
+        let (handle, parent) = (view.handle, view.dir);
+        match dir(fs, parent).map(|parent| parent.files.contains(&handle)) {
+            None => detached_files.push(handle),
+            Some(false) => fs.dirs[parent.index].as_mut().unwrap().files.push(handle),
+            Some(true) => (),
+        }
+    }
+    if !detached_dirs.is_empty() || !detached_files.is_empty() {
+        let lost = lost_and_found(fs);
+        for handle in detached_dirs {
+            fs.dirs[handle.index].as_mut().unwrap().parent = lost;
+            fs.dirs[lost.index].as_mut().unwrap().dirs.push(handle);
+        }
+        for handle in detached_files {
+            fs.files[handle.index].as_mut().unwrap().dir = lost;
+            fs.dirs[lost.index].as_mut().unwrap().files.push(handle);
+        }
+    }
+
+    // fix bad names, rename duplicates, then recompute sizes bottom-up
+    let fixed_name = |name: &str, slot: usize| match name {
+        "" => slot.to_string(),
+        _ => name.replace('/', "_"),
+    };
+    for (index, view) in fs.dirs.iter_mut().enumerate().skip(1) {
+        if let Some(view) = view.as_mut().filter(|view| bad_name(&view.name)) {
+            view.name = fixed_name(&view.name, index);
+        }
+    }
+    for (index, view) in fs.files.iter_mut().enumerate() {
+        if let Some(view) = view.as_mut().filter(|view| bad_name(&view.name)) {
+            view.name = fixed_name(&view.name, index);
+        }
+    }
+    let order: Vec<usize> = fs
+        .root()
+        .pre_order(fs)
+        .filter_map(|(_, entry)| match entry {
+            Entry::Directory(view) => Some(view.handle.index),
+            Entry::File(_) => None,
+        })
+        .collect();
+    for index in &order {
+        let view = fs.dirs[*index].as_ref().unwrap();
+        let mut names: HashSet<String> = HashSet::new();
+        let mut renames = vec![];
+        for child in &view.dirs {
+            let name = &fs.dir(*child).name;
+            if !names.insert(name.clone()) {
+                renames.push((Some(*child), None, name.clone(), child.index));
+            }
+        }
+        for child in &view.files {
+            let name = &fs.file(*child).name;
+            if !names.insert(name.clone()) {
+                renames.push((None, Some(*child), name.clone(), child.index));
+            }
+        }
+        for (child_dir, child_file, name, slot) in renames {
+            let mut renamed = format!("{}.{}", name, slot);
+            while !names.insert(renamed.clone()) {
+                renamed.push('~');
+            }
+            match (child_dir, child_file) {
+                (Some(child), _) => fs.dirs[child.index].as_mut().unwrap().name = renamed,
+                (_, Some(child)) => fs.files[child.index].as_mut().unwrap().name = renamed,
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // hard links take the size and kind of the first name of their inode
+    let fixes: Vec<(usize, usize, FileKind)> = inodes(fs.files.iter().flatten())
+        .into_values()
+        .flat_map(|links| {
+            let (size, kind) = (links[0].size, links[0].kind.clone());
+            links[1..].iter().map(move |link| (link.handle.index, size, kind.clone())).collect::<Vec<_>>()
+        })
+        .collect();
+    for (index, size, kind) in fixes {
+        let view = fs.files[index].as_mut().unwrap();
+        view.size = size;
+        view.kind = kind;
+    }
+    fs.rebuild_hard_links();
+
+    let parents = order.iter().skip(1).map(|index| (*index, fs.dirs[*index].as_ref().unwrap().parent.index)).collect();
+    let links = inodes(fs.files.iter().flatten())
+        .into_values()
+        .map(|links| (links[0].size, links.iter().map(|link| link.dir.index).collect()))
+        .collect();
+    let overcount = overcount(&parents, links);
+    let mut plain: HashMap<usize, u128> = HashMap::new();
+    for index in order.iter().rev() {
+        let view = fs.dirs[*index].as_ref().unwrap();
+        let size = view.files.iter().map(|child| fs.file(*child).size as u128).sum::<u128>()
+            + view.dirs.iter().map(|child| plain[&child.index]).sum::<u128>();
+        plain.insert(*index, size);
+        let size = counted_once(size, overcount.get(index));
+        fs.dirs[*index].as_mut().unwrap().size = usize::try_from(size).unwrap_or(usize::MAX);
+    }
+    report
+}
+
+impl BasicFileSystem {
+    /// See [check]
+    pub fn fsck(&self) -> Report {
+        check(self)
+    }
+
+    /// See [repair]
+    pub fn repair(&mut self) -> Report {
+        repair(self)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+
+    fn fixed(mut fs: BasicFileSystem) -> (Report, BasicFileSystem) {
+        let report = fs.repair();
+        assert_eq!(fs.fsck(), Report::default(), "{}", fs);
+        (report, fs)
+    }
+
+    #[test]
+    fn fsck_clean() {
+        let mut fs = example_state().fs;
+        assert!(fs.fsck().is_ok());
+        assert_eq!(fs.repair(), Report::default());
+    }
+
+    #[test]
+    fn fsck_links() {
+        // `/a/e/` claims `/d/` as its parent, `/a/` still lists it
+        let mut fs = example_state().fs;
+        fs.dirs[3].as_mut().unwrap().parent = fs.dirs[2].as_ref().unwrap().handle;
+        assert_eq!(fs.fsck().violations, [
+            Violation::Unlisted { path: "/d/e/".into() },
+            Violation::WrongListing { path: "/d/e/".into() },
+        ]);
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.lookup(fs.root(), "/d/e/i").map(|entry| entry.size()), Ok(584));
+        assert_eq!(fs.dirs[1].as_ref().unwrap().size, 94853 - 584);
+
+        // a file listed by two directories
+        let mut fs = example_state().fs;
+        let b = fs.files[0].as_ref().unwrap().handle;
+        fs.dirs[1].as_mut().unwrap().files.push(b);
+        fs.dirs[1].as_mut().unwrap().size += 14848514;
+        assert_eq!(fs.fsck().to_string(),
+            "/b.txt: listed by the wrong directory\n/: cached size 48381165 instead of 63229679\n");
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.dirs[1].as_ref().unwrap().size, 94853);
+
+        // a removed file is still listed, and a freed slot is reused behind
+        // the back of the free list
+        let mut fs = example_state().fs;
+        let i = fs.files[5].as_ref().unwrap().handle;
+        fs.remove_file(i).unwrap();
+        fs.dirs[3].as_mut().unwrap().files.push(i);
+        assert_eq!(fs.fsck().violations, [Violation::DanglingEntry { path: "/a/e/".into() }]);
+        fs.files[5] = Some(File {
+            handle: i,
+            name: "z".into(),
+            size: 1,
+            dir: fs.root(),
+            kind: FileKind::Regular,
+            inode: i.generation,
+            metadata: None,
+        });
+        assert!(fs.fsck().violations.contains(&Violation::BadFreeSlot { kind: Kind::File, index: 5 }));
+        let (_, fs) = fixed(fs);
+        assert!(fs.lookup(fs.root(), "/a/e/i").is_err());
+        assert!(fs.lookup(fs.root(), "/z").is_ok());
+    }
+
+    #[test]
+    fn fsck_detached() {
+        // `/a/` and `/a/e/` are each other's parent
+        let mut fs = example_state().fs;
+        let (a, e) = (fs.dirs[1].as_ref().unwrap().handle, fs.dirs[3].as_ref().unwrap().handle);
+        fs.dirs[1].as_mut().unwrap().parent = e;
+        let report = fs.fsck();
+        assert!(report.violations.contains(&Violation::Cycle { path: "?/e/a/".into() }));
+        assert!(report.violations.contains(&Violation::Cycle { path: "?/a/e/".into() }));
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.lookup(fs.root(), "/lost+found/a/e/i").map(|entry| entry.size()), Ok(584));
+        assert_eq!(fs.dir(a).parent, fs.dirs[4].as_ref().unwrap().handle);
+
+        // a directory that is its own parent, and a duplicate name
+        let mut fs = example_state().fs;
+        fs.dirs[2].as_mut().unwrap().parent = fs.dirs[2].as_ref().unwrap().handle;
+        fs.dirs[3].as_mut().unwrap().name = "h.lst".into();
+        let report = fs.fsck();
+        assert!(report.violations.contains(&Violation::SelfParent { path: "?/d/".into() }));
+        assert!(report.violations.contains(&Violation::DuplicateName { path: "/a/h.lst".into() }));
+        let (_, fs) = fixed(fs);
+        // `/` still lists `/d/`, which is adopted back
+        assert_eq!(fs.lookup(fs.root(), "/d/k").map(|entry| entry.size()), Ok(7214296));
+        assert_eq!(fs.lookup(fs.root(), "/a/h.lst/i").map(|entry| entry.size()), Ok(584));
+        assert_eq!(fs.lookup(fs.root(), "/a/h.lst.4").map(|entry| entry.size()), Ok(62596));
+        assert_eq!(fs.dirs[0].as_ref().unwrap().size, 48381165);
+    }
+
+    #[test]
+    fn fsck_names() {
+        // `/a/e/` is called `x/y` and `/b.txt` has no name
+        let mut fs = example_state().fs;
+        fs.dirs[3].as_mut().unwrap().name = "x/y".into();
+        fs.files[0].as_mut().unwrap().name = "".into();
+        assert_eq!(fs.fsck().to_string(), "/a/x/y/: invalid name\n/: invalid name\n");
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.lookup(fs.root(), "/a/x_y/i").map(|entry| entry.size()), Ok(584));
+        assert_eq!(fs.lookup(fs.root(), "/0").map(|entry| entry.size()), Ok(14848514));
+
+        // a fixed name may clash with another entry
+        let mut fs = example_state().fs;
+        fs.files[3].as_mut().unwrap().name = "h.lst/".into();
+        fs.files[4].as_mut().unwrap().name = "h.lst_".into();
+        let (report, fs) = fixed(fs);
+        assert_eq!(report.violations, [Violation::BadName { path: "/a/h.lst/".into() }]);
+        assert_eq!(fs.lookup(fs.root(), "/a/h.lst_").map(|entry| entry.size()), Ok(2557));
+        assert_eq!(fs.lookup(fs.root(), "/a/h.lst_.4").map(|entry| entry.size()), Ok(62596));
+    }
+
+    #[test]
+    fn fsck_hard_links() {
+        // `/d/b2` is another name of `/b.txt`, with a different size
+        let mut fs = example_state().fs;
+        let (b, mut d) = (fs.files[0].as_ref().unwrap().handle, fs.dirs[2].as_ref().unwrap().handle);
+        let b2 = d.new_hard_link("b2".into(), b, &mut fs).unwrap();
+        fs.files[b2.index].as_mut().unwrap().size = 1;
+        assert_eq!(fs.fsck().to_string(), "/d/b2: differs from the other links to its inode\n\
+            /d/: cached size 39782156 instead of 24933643\n/: cached size 48381165 instead of 33532652\n");
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.lookup(fs.root(), "/d/b2").map(|entry| entry.size()), Ok(14848514));
+        assert_eq!(fs.dirs[0].as_ref().unwrap().size, 48381165);
+
+        // `/d/b2` does not share the inode anymore, but is still recorded
+        let mut fs = example_state().fs;
+        let (b, mut d) = (fs.files[0].as_ref().unwrap().handle, fs.dirs[2].as_ref().unwrap().handle);
+        let b2 = d.new_hard_link("b2".into(), b, &mut fs).unwrap();
+        fs.files[b2.index].as_mut().unwrap().inode = 99;
+        assert_eq!(fs.fsck().to_string(),
+            "inode 2: hard links are not recorded\n/: cached size 48381165 instead of 63229679\n");
+        let (_, fs) = fixed(fs);
+        assert_eq!(fs.dirs[0].as_ref().unwrap().size, 63229679);
+    }
+
+    /// Mutations are checked after the fact in this crate's tests
+    #[test]
+    #[should_panic(expected = "cached size")]
+    fn fsck_hook() {
+        let mut fs = example_state().fs;
+        fs.dirs[1].as_mut().unwrap().size = 0;
+        let _ = fs.root().new_file("x".into(), 1, &mut fs);
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::fs::{BasicFileSystem, DirectoryHandle, FileHandle};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct ImportOptions {
+    /// Descend into symlinked directories and count the size of symlink
+    /// targets, rather than the size of the links themselves
+    pub follow_symlinks: bool,
+    /// Do not cross into other mounted filesystems (unix only)
+    pub one_file_system: bool,
+    /// Record unreadable entries in the [ImportReport] instead of failing
+    pub skip_unreadable: bool,
+}
+
+/// Entries that were left out of an import
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct ImportReport {
+    /// Entries that could not be read, with the reason
+    pub skipped: Vec<(PathBuf, io::ErrorKind)>,
+    /// Directories that were not descended into because they live on another
+    /// filesystem or were already visited through a symlink
+    pub pruned: Vec<PathBuf>,
+}
+
+/// Identity of an entry on disk, used to avoid symlink loops, to stay on one
+/// filesystem and to recognize hard links
+#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+struct DeviceInode(u64, u64);
+
+#[cfg(unix)]
+fn device_inode(metadata: &std::fs::Metadata) -> Option<DeviceInode> {
+    use std::os::unix::fs::MetadataExt;
+    Some(DeviceInode(metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn device_inode(_metadata: &std::fs::Metadata) -> Option<DeviceInode> {
+    None
+}
+
+/// Build a [BasicFileSystem] mirroring the directory at `path`, which becomes
+/// the root `/` of the model. File sizes are apparent sizes in bytes, and
+/// the names of a file with several hard links become hard links sharing its
+/// size (unix only).
+pub fn import(
+    path: impl AsRef<std::path::Path>,
+    options: &ImportOptions,
+) -> Result<(BasicFileSystem, ImportReport), Error> {
+    let mut fs = BasicFileSystem::new();
+    let mut report = ImportReport::default();
+    let path = path.as_ref();
+    let io_error = |path: &std::path::Path, error: io::Error| Error::Io {
+        path: path.display().to_string(),
+        kind: error.kind(),
+    };
+
+    let metadata = std::fs::metadata(path).map_err(|error| io_error(path, error))?;
+    let device = device_inode(&metadata).map(|DeviceInode(device, _)| device);
+    let mut visited: HashSet<DeviceInode> = device_inode(&metadata).into_iter().collect();
+    let mut files: HashMap<DeviceInode, FileHandle> = HashMap::new();
+    let mut pending: Vec<(PathBuf, DirectoryHandle)> = vec![(path.into(), fs.root())];
+
+    while let Some((path, mut dir)) = pending.pop() {
+        let entries = match std::fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(error) if options.skip_unreadable => {
+                report.skipped.push((path, error.kind()));
+                continue;
+            }
+            Err(error) => return Err(io_error(&path, error)),
+        };
+        for entry in entries {
+            // the path of the entry when it could be read, of the directory otherwise
+            let entry = entry.map_err(|error| (path.clone(), error)).and_then(|entry| {
+                let metadata = if options.follow_symlinks {
+                    std::fs::metadata(entry.path())
+                } else {
+                    entry.metadata()
+                };
+                match metadata {
+                    Ok(metadata) => Ok((entry, metadata)),
+                    Err(error) => Err((entry.path(), error)),
+                }
+            });
+            let (entry, metadata) = match entry {
+                Ok(entry) => entry,
+                Err((failed, error)) if options.skip_unreadable => {
+                    report.skipped.push((failed, error.kind()));
+                    continue;
+                }
+                Err((failed, error)) => return Err(io_error(&failed, error)),
+            };
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !metadata.is_dir() {
+                // a followed symlink still counts the size of its target again
+                let symlink = entry.file_type().is_ok_and(|kind| kind.is_symlink());
+                let identity = device_inode(&metadata).filter(|_| !symlink);
+                match identity.and_then(|identity| files.get(&identity)) {
+                    Some(file) => {
+                        dir.new_hard_link(name, *file, &mut fs)?;
+                    }
+                    None => {
+                        let file = dir.new_file(name, metadata.len() as usize, &mut fs)?;
+                        if let Some(identity) = identity {
+                            files.insert(identity, file);
+                        }
+                    }
+                }
+                continue;
+            }
+            let identity = device_inode(&metadata);
+            let other_device = identity.is_some_and(|DeviceInode(other, _)| Some(other) != device);
+            let revisited = identity.is_some_and(|identity| !visited.insert(identity));
+            let child = dir.new_directory(name, &mut fs)?;
+            if (options.one_file_system && other_device) || revisited {
+                report.pruned.push(entry.path());
+                continue;
+            }
+            pending.push((entry.path(), child));
+        }
+    }
+    Ok((fs, report))
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fs::{Entry, Path, Tree};
+
+    /// Scratch directory removed when dropped
+    struct Scratch(PathBuf);
+
+    impl Scratch {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("day7-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Scratch(path)
+        }
+
+        fn file(&self, path: &str, size: usize) {
+            std::fs::write(self.0.join(path), vec![0u8; size]).unwrap();
+        }
+
+        fn dir(&self, path: &str) {
+            std::fs::create_dir_all(self.0.join(path)).unwrap();
+        }
+    }
+
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn import_directory() {
+        let scratch = Scratch::new("import");
+        scratch.dir("a/e");
+        scratch.dir("d");
+        scratch.file("a/e/i", 584);
+        scratch.file("a/f", 2911);
+        scratch.file("b.txt", 1484);
+
+        let (fs, report) = import(&scratch.0, &ImportOptions::default()).unwrap();
+        assert_eq!(report, ImportReport::default());
+        assert_eq!(fs.root().size(&fs), 584 + 2911 + 1484);
+
+        let sizes: HashSet<(String, usize)> = fs
+            .dir_sizes()
+            .into_iter()
+            .map(|(dir, size)| (dir.abspath(&fs), size))
+            .collect();
+        assert_eq!(sizes, HashSet::from([
+            ("/".into(), 584 + 2911 + 1484),
+            ("/a/".into(), 584 + 2911),
+            ("/a/e/".into(), 584),
+            ("/d/".into(), 0),
+        ]));
+    }
+
+    #[test]
+    fn import_missing() {
+        let scratch = Scratch::new("import-missing");
+        let missing = scratch.0.join("missing");
+        let error = import(&missing, &ImportOptions::default()).unwrap_err();
+        assert_eq!(error, Error::Io { path: missing.display().to_string(), kind: io::ErrorKind::NotFound });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn import_hard_links() {
+        let scratch = Scratch::new("import-hard-links");
+        scratch.dir("a");
+        scratch.dir("b");
+        scratch.file("a/f", 100);
+        std::fs::hard_link(scratch.0.join("a/f"), scratch.0.join("b/g")).unwrap();
+
+        let (fs, _) = import(&scratch.0, &ImportOptions::default()).unwrap();
+        assert_eq!(fs.root().size(&fs), 100);
+        let g = match fs.lookup(fs.root(), "/b/g") {
+            Ok(Entry::File(g)) => g,
+            _ => panic!("{}", fs),
+        };
+        assert_eq!(g.handle.links(&fs).len(), 2);
+        assert_eq!(g.dir.size(&fs), 100);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn import_symlinks() {
+        let scratch = Scratch::new("import-symlinks");
+        scratch.dir("a");
+        scratch.file("a/f", 100);
+        std::os::unix::fs::symlink(scratch.0.join("a"), scratch.0.join("a/loop")).unwrap();
+        std::os::unix::fs::symlink(scratch.0.join("a/f"), scratch.0.join("g")).unwrap();
+
+        let (fs, report) = import(&scratch.0, &ImportOptions::default()).unwrap();
+        assert!(report.pruned.is_empty());
+        let link_size = |path: &str| std::fs::symlink_metadata(scratch.0.join(path)).unwrap().len() as usize;
+        assert_eq!(fs.root().size(&fs), 100 + link_size("a/loop") + link_size("g"));
+
+        let options = ImportOptions { follow_symlinks: true, ..Default::default() };
+        let (fs, report) = import(&scratch.0, &options).unwrap();
+        assert_eq!(report.pruned, [scratch.0.join("a/loop")]);
+        assert_eq!(fs.root().size(&fs), 200);
+
+        // a dangling link is reported under its own path
+        std::os::unix::fs::symlink(scratch.0.join("missing"), scratch.0.join("a/h")).unwrap();
+        let error = import(&scratch.0, &options).unwrap_err();
+        assert_eq!(error, Error::Io { path: scratch.0.join("a/h").display().to_string(), kind: io::ErrorKind::NotFound });
+        let options = ImportOptions { skip_unreadable: true, ..options };
+        let (fs, report) = import(&scratch.0, &options).unwrap();
+        assert_eq!(report.skipped, [(scratch.0.join("a/h"), io::ErrorKind::NotFound)]);
+        assert_eq!(fs.root().size(&fs), 200);
+    }
+}
@@ -0,0 +1,279 @@
+use std::io::BufRead;
+
+use crate::error::Error;
+use crate::fs::{ConflictPolicy, DirectoryHandle};
+use crate::parse::ShellState;
+
+/// Number of commands between two copies of the state kept by a [Journal]
+pub const CHECKPOINT_INTERVAL: usize = 64;
+
+/// A `$` line of a transcript with the lines it listed
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Command {
+    /// 1-based number of the `$` line in the transcript
+    pub line: usize,
+    /// The `$` line and the entries listed after it, without line endings
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    state: ShellState,
+    listing: Option<DirectoryHandle>,
+}
+
+/// Transcript replay that can go back in time.
+///
+/// Every command applied to the [ShellState] is recorded, so the state can be
+/// rewound to what it was after any number of commands and moved forward
+/// again. A copy of the state is kept every [CHECKPOINT_INTERVAL] commands,
+/// a rewind replays the commands recorded after the closest one. The copies
+/// share the counter of generations with the state, see
+/// [crate::fs::BasicFileSystem::snapshot], so an entry created again by a
+/// replay gets a new handle. A handle taken from the state stays valid only
+/// if its entry was created before the copy replayed from.
+///
+/// A line that fails is not recorded and leaves the state as it was before
+/// that line, which makes it easy to find the command that broke a transcript.
+#[derive(Debug)]
+pub struct Journal {
+    state: ShellState,
+    listing: Option<DirectoryHandle>,
+    /// State before command `i * CHECKPOINT_INTERVAL`
+    checkpoints: Vec<Checkpoint>,
+    commands: Vec<Command>,
+    /// Number of commands applied to `state`
+    position: usize,
+    /// Number of lines pushed so far
+    line: usize,
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Self::with_policy(ConflictPolicy::default())
+    }
+
+    pub fn with_policy(conflict_policy: ConflictPolicy) -> Self {
+        let state = ShellState::new(conflict_policy);
+        Journal {
+            checkpoints: vec![Checkpoint { state: state.clone(), listing: None }],
+            state,
+            listing: None,
+            commands: vec![],
+            position: 0,
+            line: 0,
+        }
+    }
+
+    pub fn state(&self) -> &ShellState {
+        &self.state
+    }
+
+    /// Commands recorded so far, including those undone
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// Number of commands applied to the current state
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Apply the next line of the transcript. When commands were undone, a
+    /// new command discards them and an entry line is an error.
+    pub fn push_line(&mut self, text: &str) -> Result<(), Error> {
+        self.line += 1;
+        let text = text.trim_end_matches(['\r', '\n']);
+        let content = text.trim_start();
+        if content.is_empty() {
+            return Ok(());
+        }
+        let command = content.starts_with('$');
+        if self.position < self.commands.len() {
+            if !command {
+                let column = text.len() - content.len() + 1;
+                return Err(Error::Malformed { line: self.line, column, text: text.into() });
+            }
+            self.commands.truncate(self.position);
+            self.checkpoints.truncate(self.position / CHECKPOINT_INTERVAL + 1);
+        }
+        if command && self.position == self.checkpoints.len() * CHECKPOINT_INTERVAL {
+            self.checkpoints.push(Checkpoint { state: self.state.clone(), listing: self.listing });
+        }
+        if let Err(error) = self.state.apply_line(text, self.line, &mut self.listing) {
+            // the line may have been applied in part
+            self.replay(self.position);
+            return Err(error);
+        }
+        if command {
+            self.commands.push(Command { line: self.line, lines: vec![] });
+            self.position += 1;
+        }
+        self.commands[self.position - 1].lines.push(text.into());
+        Ok(())
+    }
+
+    /// Apply every line of `reader`, stopping at the first error
+    pub fn extend<R: BufRead>(&mut self, mut reader: R) -> Result<(), Error> {
+        let mut buffer = String::new();
+        loop {
+            buffer.clear();
+            let read = reader.read_line(&mut buffer).map_err(|error| Error::Read {
+                line: self.line + 1,
+                kind: error.kind(),
+            })?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.push_line(&buffer)?;
+        }
+    }
+
+    /// Bring the state back to what it was after the first `position`
+    /// commands, returns `false` if fewer commands were recorded
+    pub fn rewind(&mut self, position: usize) -> bool {
+        if position > self.commands.len() {
+            return false;
+        }
+        if position != self.position {
+            self.replay(position);
+        }
+        true
+    }
+
+    /// Undo the last command applied, returns `false` if there is none
+    pub fn undo(&mut self) -> bool {
+        self.position > 0 && self.rewind(self.position - 1)
+    }
+
+    /// Apply again the last command undone, returns `false` if there is none
+    pub fn redo(&mut self) -> bool {
+        self.rewind(self.position + 1)
+    }
+
+    /// Rebuild the state from the closest checkpoint up to `position`
+    fn replay(&mut self, position: usize) {
+        let first = (position / CHECKPOINT_INTERVAL).min(self.checkpoints.len() - 1);
+        let checkpoint = self.checkpoints[first].clone();
+        self.state = checkpoint.state;
+        self.listing = checkpoint.listing;
+        for command in &self.commands[first * CHECKPOINT_INTERVAL..position] {
+            for (offset, text) in command.lines.iter().enumerate() {
+                self.state
+                    .apply_line(text, command.line + offset, &mut self.listing)
+                    .expect("recorded lines apply again");
+            }
+        }
+        self.position = position;
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::diff::diff;
+    use crate::fixtures::{example_state, EXAMPLE};
+    use crate::fs::{HandleError, Path};
+    use crate::parse::parse;
+
+    fn size(journal: &Journal) -> usize {
+        journal.state().fs.root().view(&journal.state().fs).unwrap().size
+    }
+
+    #[test]
+    fn journal_undo_redo() {
+        let mut journal = Journal::new();
+        journal.extend(EXAMPLE.as_bytes()).unwrap();
+        assert_eq!(journal.commands().len(), 10);
+        assert_eq!(journal.commands()[1], Command {
+            line: 2,
+            lines: vec!["$ ls".into(), "dir a".into(), "14848514 b.txt".into(), "8504156 c.dat".into(), "dir d".into()],
+        });
+        assert_eq!(size(&journal), 48381165);
+
+        assert!(journal.rewind(5));
+        assert_eq!(journal.state().cwd.abspath(&journal.state().fs), "/a/e/");
+        assert_eq!(size(&journal), 23446939);
+        assert!(journal.undo());
+        assert_eq!(journal.state().cwd.abspath(&journal.state().fs), "/a/");
+        assert!(journal.rewind(0));
+        assert_eq!(size(&journal), 0);
+        assert!(!journal.undo());
+        assert!(journal.redo() && journal.redo());
+        assert_eq!(size(&journal), 23352670);
+        assert!(!journal.rewind(11));
+        assert!(journal.rewind(10));
+        assert!(!journal.redo());
+        assert_eq!(journal.state().fs.root().abspath(&journal.state().fs), "/");
+        assert!(diff(&journal.state().fs, &example_state().fs).is_empty());
+
+        // a new command discards the commands undone
+        assert!(journal.rewind(2));
+        assert!(matches!(journal.push_line("1 x\n"), Err(Error::Malformed { line: 24, .. })));
+        journal.push_line("$ rm c.dat\n").unwrap();
+        assert_eq!((journal.position(), journal.commands().len()), (3, 3));
+        assert_eq!(size(&journal), 14848514);
+        assert!(journal.undo());
+        assert_eq!(size(&journal), 23352670);
+
+        // entries created again get new handles, the old ones are stale
+        let a = journal.state().resolve_directory("/a").unwrap();
+        assert!(journal.rewind(1));
+        journal.push_line("$ ls").unwrap();
+        journal.push_line("dir a").unwrap();
+        assert_eq!(a.view(&journal.state().fs), Err(HandleError::Stale));
+        let a = journal.state().resolve_directory("/a").unwrap();
+        assert!(journal.undo() && journal.redo());
+        assert_eq!(a.view(&journal.state().fs), Err(HandleError::Stale));
+        assert!(journal.state().resolve_directory("/a").is_ok());
+    }
+
+    #[test]
+    fn journal_errors_and_checkpoints() {
+        let mut journal = Journal::new();
+        journal.extend("$ ls\ndir a\n1 b\n".as_bytes()).unwrap();
+        // a failing entry leaves the entries listed before it
+        let error = journal.extend("2 c\nx y z\n3 d\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, Error::Malformed { line: 5, .. }));
+        assert_eq!(size(&journal), 3);
+        assert_eq!(journal.commands()[0].lines.len(), 4);
+        assert_eq!(journal.push_line("$ cd x"), Err(Error::UnknownDirectory("/x".into())));
+        assert_eq!(journal.commands().len(), 1);
+        journal.push_line("3 d").unwrap();
+        assert_eq!(size(&journal), 6);
+        let error = journal.extend(&b"4 e\n\xff\n"[..]).unwrap_err();
+        assert_eq!(error, Error::Read { line: 9, kind: std::io::ErrorKind::InvalidData });
+        assert_eq!(size(&journal), 10);
+
+        // enough commands for several checkpoints, every rewind agrees with
+        // a fresh replay of the same prefix
+        let mut transcript = String::new();
+        for i in 0..150 {
+            transcript.push_str(&format!("$ mkdir d{0}\n$ cd d{0}\n$ ls\n{0} f\n", i));
+            if i % 7 == 0 {
+                transcript.push_str(&format!("$ cd ..\n$ rm d{}/f\n", i));
+            }
+        }
+        let mut journal = Journal::new();
+        journal.extend(transcript.as_bytes()).unwrap();
+        assert!(journal.checkpoints.len() > 2);
+        for position in [0, 1, 63, 64, 65, 128, 300, journal.commands().len(), 200, 7] {
+            assert!(journal.rewind(position));
+            let prefix: String = journal.commands()[..position]
+                .iter()
+                .flat_map(|command| &command.lines)
+                .map(|line| format!("{}\n", line))
+                .collect();
+            let expected = parse(prefix).unwrap();
+            assert!(diff(&journal.state().fs, &expected.fs).is_empty());
+            assert_eq!(journal.state().cwd.abspath(&journal.state().fs), expected.cwd.abspath(&expected.fs));
+        }
+    }
+}
@@ -0,0 +1,21 @@
+pub mod analysis;
+pub mod cleanup;
+pub mod cow;
+pub mod diff;
+pub mod emit;
+pub mod error;
+pub mod frozen;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod fs;
+pub mod fsck;
+pub mod import;
+pub mod journal;
+pub mod parse;
+#[cfg(feature = "serde")]
+pub mod persist;
+pub mod query;
+pub mod render;
+pub mod repl;
+pub mod report;
+pub mod traverse;
@@ -1,54 +1,65 @@
 use std::io::{BufRead, BufReader};
-mod fs;
-mod parse;
-use crate::fs::Tree;
-
-fn part1(mut reader: impl BufRead) -> usize {
-    let mut input = String::new();
-    reader.read_to_string(&mut input).unwrap();
-    let state = parse::parse(input).unwrap();
-    let root = state.fs.root();
-    root.fold(&state.fs, 0, &|accum, entry| {
-        match entry {
-            fs::Entry::File(_) => accum,
-            fs::Entry::Directory(directory) => {
-                let dsize = directory.handle.size(&state.fs); // FIXME inefficient
-                if dsize <= 100_000 {
-                    accum + dsize
-                } else {
-                    accum
-                }
-            }
-        }
-    })
+use day7::analysis;
+use day7::fs::{Path, Tree};
+use day7::import::{import, ImportOptions};
+use day7::parse::{self, ShellStream};
+use day7::repl::Repl;
+
+fn part1(reader: impl BufRead) -> usize {
+    let state = ShellStream::new(reader).finish().unwrap();
+    analysis::total_of_small_dirs(&state.fs, 100_000)
 }
 
-fn part2(mut reader: impl BufRead) -> usize {
-    let mut input = String::new();
-    reader.read_to_string(&mut input).unwrap();
-    let state = parse::parse(input).unwrap();
-    let root = state.fs.root();
-    let mut dir_sizes = root.fold(&state.fs, vec![], &|mut accum, entry| {
-        match entry {
-            fs::Entry::File(_) => (),
-            fs::Entry::Directory(directory) => {
-                accum.push(directory.handle.size(&state.fs)); // FIXME inefficient
-            }
-        }
-        accum
-    });
-    let used_size = dir_sizes.iter().max().unwrap();
-    let (total_size, required_size) = (70_000_000usize, 30_000_000usize);
-    let unused_size = total_size - used_size;
-    if required_size <= unused_size {
-        return 0; // no need to delete any directory
-    }
-    let freeup_size = required_size - unused_size;
+/// Size of the disk the transcript was taken on
+const DISK_CAPACITY: usize = 70_000_000;
+
+fn part2(reader: impl BufRead) -> usize {
+    let state = ShellStream::new(reader).with_capacity(DISK_CAPACITY).finish().unwrap();
+    analysis::smallest_dir_to_free(&state.fs, DISK_CAPACITY, 30_000_000).map(|(_, size)| size).unwrap_or(0)
+}
+
+/// Print the cumulative size of every directory below `path`, like `du`
+fn du(path: &str) {
+    let options = ImportOptions { skip_unreadable: true, ..Default::default() };
+    let (fs, report) = import(path, &options).unwrap();
+    let mut dir_sizes: Vec<(String, usize)> = fs.dir_sizes()
+        .into_iter()
+        .map(|(dir, dsize)| (dir.abspath(&fs), dsize))
+        .collect();
     dir_sizes.sort();
-    *dir_sizes.iter().skip_while(|dsize| **dsize < freeup_size).nth(0).unwrap_or(&0)
+    for (dir, dsize) in dir_sizes {
+        println!("{}\t{}", dsize, dir);
+    }
+    for (path, kind) in report.skipped {
+        eprintln!("skipped {}: {}", path.display(), kind);
+    }
+    eprintln!("total {}", fs.root().size(&fs));
+}
+
+/// Explore the filesystem described by the transcript at `path` interactively
+fn repl(path: &str) {
+    let input = std::fs::read_to_string(path).unwrap();
+    let mut repl = Repl::new(parse::parse(input).unwrap());
+    let stdin = std::io::stdin();
+    repl.run(stdin.lock(), std::io::stdout()).unwrap();
+}
+
+/// Print what changed between the transcripts at `before` and `after`
+fn diff(before: &str, after: &str) {
+    let before = parse::parse(std::fs::read_to_string(before).unwrap()).unwrap();
+    let after = parse::parse(std::fs::read_to_string(after).unwrap()).unwrap();
+    print!("{}", day7::diff::diff(&before.fs, &after.fs));
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[1..] {
+        ["du", path] => return du(path),
+        ["diff", before, after] => return diff(before, after),
+        ["repl"] => return repl("input/day7.txt"),
+        ["repl", path] => return repl(path),
+        _ => (),
+    }
     let reader = BufReader::new(std::fs::File::open("input/day7.txt").unwrap());
     println!("part1 = {}", part1(reader));
     let reader = BufReader::new(std::fs::File::open("input/day7.txt").unwrap());
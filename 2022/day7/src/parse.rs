@@ -1,22 +1,30 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::BufRead;
+
 use nom::IResult;
 use nom::branch::alt;
-use nom::bytes::complete::tag;
+use nom::bytes::complete::{is_not, tag, take_until};
 use nom::character::complete::{
-    alphanumeric1,
     digit1,
     line_ending,
     multispace0,
+    none_of,
     not_line_ending,
+    one_of,
+    space0,
     space1,
 };
-use nom::multi::{many0, fold_many0};
-use nom::sequence::{delimited, separated_pair};
+use nom::combinator::{cut, eof, map, map_res, opt, recognize, verify};
+use nom::multi::{many0, many1};
+use nom::sequence::{delimited, preceded, separated_pair, terminated, tuple};
 
-use crate::fs::{BasicFileSystem, DirectoryHandle};
+use crate::error::Error;
+use crate::fs::{BasicFileSystem, ConflictPolicy, DirectoryHandle, Entry, FileHandle, LookupError, Metadata, Path};
 
 #[derive(PartialEq, Eq, Debug)]
 struct ChangeDirectoryOperation<'a> {
-    path: &'a str,
+    path: Cow<'a, str>,
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -30,108 +38,613 @@ struct DirectoryEntry<'a> {
     name: &'a str,
 }
 
+/// A line of `ls -l --time-style=long-iso`, prefixed by the inode number
+/// when listed with `ls -li`
+#[derive(PartialEq, Eq, Debug)]
+struct LongEntry<'a> {
+    inode: Option<usize>,
+    /// `-` for a file, `d` for a directory, `l` for a symbolic link
+    kind: char,
+    mode: &'a str,
+    owner: &'a str,
+    group: &'a str,
+    size: usize,
+    mtime: &'a str,
+    name: &'a str,
+    target: Option<&'a str>,
+}
+
 #[derive(PartialEq, Eq, Debug)]
 enum ListEntry<'a> {
     File(FileEntry<'a>),
     Directory(DirectoryEntry<'a>),
+    Long(LongEntry<'a>),
+    /// `total N`, the blocks used by an `ls -l` listing, which adds nothing
+    Total,
 }
 
 #[derive(PartialEq, Eq, Debug)]
 struct ListOperation<'a> {
+    path: Option<Cow<'a, str>>,
     entries: Vec<ListEntry<'a>>,
 }
 
+#[derive(PartialEq, Eq, Debug)]
+struct MakeDirectoryOperation<'a> {
+    path: Cow<'a, str>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct TouchOperation<'a> {
+    size: usize,
+    path: Cow<'a, str>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct RemoveOperation<'a> {
+    path: Cow<'a, str>,
+}
+
+#[derive(PartialEq, Eq, Debug)]
+struct MoveOperation<'a> {
+    source: Cow<'a, str>,
+    dest: Cow<'a, str>,
+}
+
 #[derive(PartialEq, Eq, Debug)]
 enum ShellOperation<'a> {
     ChangeDirectory(ChangeDirectoryOperation<'a>),
     List(ListOperation<'a>),
+    MakeDirectory(MakeDirectoryOperation<'a>),
+    Touch(TouchOperation<'a>),
+    Remove(RemoveOperation<'a>),
+    Move(MoveOperation<'a>),
+}
+
+/// A word of an `ls -l` line, i.e. anything up to the next whitespace
+fn word(input: &str) -> IResult<&str, &str> {
+    is_not(" \t\r\n")(input)
+}
+
+/// The name a quoted path stands for
+fn unquote(quoted: &str) -> Cow<'_, str> {
+    if !quoted.contains(['\'', '\\']) {
+        return Cow::Borrowed(quoted);
+    }
+    let mut name = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => name.extend(chars.by_ref().take_while(|c| *c != '\'')),
+            '\\' => name.extend(chars.next()),
+            c => name.push(c),
+        }
+    }
+    Cow::Owned(name)
+}
+
+/// A path argument, quoted as in a shell: whitespace, `'` and `\` are part
+/// of the path between single quotes or right after a `\`
+fn path(input: &str) -> IResult<&str, Cow<'_, str>> {
+    let plain = is_not(" \t\r\n'\\");
+    let quoted = recognize(delimited(tag("'"), opt(is_not("'\r\n")), tag("'")));
+    let escaped = recognize(preceded(tag("\\"), none_of("\r\n")));
+    map(recognize(many1(alt((plain, quoted, escaped)))), unquote)(input)
 }
 
-fn parse_cd(input: &str) -> IResult<&str, ShellOperation> {
+/// A number of bytes, an error if it does not fit a `usize`
+fn size(input: &str) -> IResult<&str, usize> {
+    map_res(digit1, str::parse)(input)
+}
+
+fn parse_cd(input: &str) -> IResult<&str, ShellOperation<'_>> {
     let (input, _) = tag("cd")(input)?;
-    let (input, path) = delimited(
-        space1,
-        alt((alphanumeric1, tag("/"), tag(".."))),
-        line_ending,
-    )(input)?;
+    let (input, path) = cut(delimited(space1, path, line_ending))(input)?;
     Ok((input, ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path })))
 }
 
-fn file_entry(input: &str) -> IResult<&str, ListEntry> {
-    let (input, (size, name)) = separated_pair(digit1, space1, not_line_ending)(input)?;
+fn file_entry(input: &str) -> IResult<&str, ListEntry<'_>> {
+    let (input, (size, name)) = separated_pair(size, space1, not_line_ending)(input)?;
     let (input, _) = line_ending(input)?;
-    Ok((input, ListEntry::File(FileEntry {name, size: size.parse().unwrap_or(0)})))
+    Ok((input, ListEntry::File(FileEntry { name, size })))
 }
 
-fn directory_entry(input: &str) -> IResult<&str, ListEntry> {
+fn directory_entry(input: &str) -> IResult<&str, ListEntry<'_>> {
     let (input, (_, name)) = separated_pair(tag("dir"), space1, not_line_ending)(input)?;
     let (input, _) = line_ending(input)?;
     Ok((input, ListEntry::Directory(DirectoryEntry { name })))
 }
 
-fn parse_ls(input: &str) -> IResult<&str, ShellOperation> {
-    let (input, _) = tag("ls")(input)?;
+fn long_entry(input: &str) -> IResult<&str, ListEntry<'_>> {
+    let (input, inode) = opt(terminated(map_res(digit1, str::parse), space1))(input)?;
+    let (input, kind) = one_of("-dl")(input)?;
+    let (input, mode) = verify(word, |mode: &str| mode.len() >= 9)(input)?;
+    let (input, (_, _, _, owner, _, group, _, size, _)) =
+        tuple((space1, digit1, space1, word, space1, word, space1, size, space1))(input)?;
+    let (input, mtime) = recognize(separated_pair(word, space1, word))(input)?;
+    let (input, _) = space1(input)?;
+    let (input, (name, target)) = if kind == 'l' {
+        map(separated_pair(take_until(" -> "), tag(" -> "), not_line_ending), |(name, target)| (name, Some(target)))(input)?
+    } else {
+        map(not_line_ending, |name| (name, None))(input)?
+    };
     let (input, _) = line_ending(input)?;
-    let (input, entries) = many0(alt((file_entry, directory_entry)))(input)?;
-    Ok((input, ShellOperation::List(ListOperation { entries })))
+    Ok((input, ListEntry::Long(LongEntry {
+        inode,
+        kind,
+        mode,
+        owner,
+        group,
+        size,
+        mtime,
+        name,
+        target,
+    })))
+}
+
+fn total_entry(input: &str) -> IResult<&str, ListEntry<'_>> {
+    let (input, _) = tuple((tag("total"), space1, digit1, line_ending))(input)?;
+    Ok((input, ListEntry::Total))
+}
+
+/// A line listed by `ls`, in the short or the long format
+fn list_entry(input: &str) -> IResult<&str, ListEntry<'_>> {
+    alt((long_entry, file_entry, directory_entry, total_entry))(input)
+}
+
+fn parse_ls(input: &str) -> IResult<&str, ShellOperation<'_>> {
+    let (input, _) = tag("ls")(input)?;
+    // options such as `-li` only change the format of the entries
+    let (input, _) = many0(preceded(space1, recognize(preceded(tag("-"), word))))(input)?;
+    let (input, path) = cut(terminated(opt(preceded(space1, path)), line_ending))(input)?;
+    let (input, entries) = many0(list_entry)(input)?;
+    Ok((input, ShellOperation::List(ListOperation { path, entries })))
+}
+
+fn parse_mkdir(input: &str) -> IResult<&str, ShellOperation<'_>> {
+    let (input, _) = tag("mkdir")(input)?;
+    let (input, path) = cut(delimited(space1, path, line_ending))(input)?;
+    Ok((input, ShellOperation::MakeDirectory(MakeDirectoryOperation { path })))
+}
+
+fn parse_touch(input: &str) -> IResult<&str, ShellOperation<'_>> {
+    let (input, _) = tag("touch")(input)?;
+    let (input, (_, size, _, path, _)) = cut(tuple((space1, size, space1, path, line_ending)))(input)?;
+    Ok((input, ShellOperation::Touch(TouchOperation { size, path })))
+}
+
+fn parse_rm(input: &str) -> IResult<&str, ShellOperation<'_>> {
+    let (input, _) = tag("rm")(input)?;
+    let (input, path) = cut(delimited(space1, path, line_ending))(input)?;
+    Ok((input, ShellOperation::Remove(RemoveOperation { path })))
 }
 
-fn parse_cmd(input: &str) -> IResult<&str, ShellOperation> {
+fn parse_mv(input: &str) -> IResult<&str, ShellOperation<'_>> {
+    let (input, _) = tag("mv")(input)?;
+    let (input, (_, source, _, dest, _)) = cut(tuple((space1, path, space1, path, line_ending)))(input)?;
+    Ok((input, ShellOperation::Move(MoveOperation { source, dest })))
+}
+
+fn parse_cmd(input: &str) -> IResult<&str, ShellOperation<'_>> {
     // skip any whitespaces
     let (input, _) = multispace0(input)?;
     // parse prompt
     let (input, _) = tag("$ ")(input)?;
     // parse command
-    alt((parse_cd, parse_ls))(input)
+    alt((parse_cd, parse_ls, parse_mkdir, parse_touch, parse_rm, parse_mv))(input)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ShellState {
     pub fs: BasicFileSystem,
     pub cwd: DirectoryHandle,
+    /// First file listed with each inode number by `ls -li`
+    pub(crate) inodes: HashMap<usize, FileHandle>,
 }
 
 impl ShellState {
-    fn new() -> Self {
-        let fs = BasicFileSystem::new();
+    pub(crate) fn new(conflict_policy: ConflictPolicy) -> Self {
+        let fs = BasicFileSystem::with_conflict_policy(conflict_policy);
         let cwd = fs.root();
-        ShellState { fs, cwd }
+        ShellState { fs, cwd, inodes: HashMap::new() }
     }
 }
 
-pub fn parse(input: String) -> Result<ShellState, String> {
-    let fold_fn = move |mut state: ShellState, operation: ShellOperation| -> ShellState {
+impl ShellState {
+    /// Resolve `path` to a directory the way `cd` does: `..` above the root
+    /// is an error and every component must be an existing directory
+    pub fn resolve_directory(&self, path: &str) -> Result<DirectoryHandle, Error> {
+        let mut cwd = if path.starts_with('/') { self.fs.root() } else { self.cwd };
+        for component in path.split('/') {
+            match component {
+                "" | "." => (),
+                ".." => {
+                    if cwd == self.fs.root() {
+                        return Err(Error::AboveRoot);
+                    }
+                    cwd = cwd.parent(&self.fs);
+                },
+                name => {
+                    cwd = match cwd.lookup(name, &self.fs) {
+                        Ok(Entry::Directory(directory)) => directory.handle,
+                        Ok(Entry::File(file)) => {
+                            let path = file.handle.abspath(&self.fs);
+                            return Err(Error::Lookup(LookupError::NotADirectory(path)));
+                        },
+                        Err(LookupError::NotFound(path)) => return Err(Error::UnknownDirectory(path)),
+                        Err(error) => return Err(Error::Lookup(error)),
+                    };
+                },
+            }
+        }
+        Ok(cwd)
+    }
+
+    /// Split `path` into the directory that holds it and its last component
+    fn resolve_parent<'p>(&self, path: &'p str) -> Result<(DirectoryHandle, &'p str), Error> {
+        let path = if path.len() > 1 { path.trim_end_matches('/') } else { path };
+        let (parent, name) = match path.rsplit_once('/') {
+            Some(("", name)) => (self.fs.root(), name),
+            Some((parent, name)) => (self.resolve_directory(parent)?, name),
+            None => (self.cwd, path),
+        };
+        match name {
+            "" => Err(Error::RootDirectory),
+            "." | ".." => Err(Error::NameConflict(path.into())),
+            name => Ok((parent, name)),
+        }
+    }
+
+    /// Directory whose entries an `ls` lists
+    fn list_target(&self, op: &ListOperation) -> Result<DirectoryHandle, Error> {
+        match &op.path {
+            Some(path) => self.resolve_directory(path),
+            None => Ok(self.cwd),
+        }
+    }
+
+    fn add_entry(&mut self, mut dir: DirectoryHandle, entry: ListEntry) -> Result<(), Error> {
+        match entry {
+            ListEntry::File(file) => {
+                dir.new_file(file.name.into(), file.size, &mut self.fs)?;
+            },
+            ListEntry::Directory(directory) => {
+                dir.new_directory(directory.name.into(), &mut self.fs)?;
+            },
+            ListEntry::Long(entry) => self.add_long_entry(dir, entry)?,
+            ListEntry::Total => (),
+        };
+        Ok(())
+    }
+
+    /// Add an entry with its metadata. A file listed with an inode number
+    /// that was seen before becomes a hard link to the first file listed
+    /// with it.
+    fn add_long_entry(&mut self, mut dir: DirectoryHandle, entry: LongEntry) -> Result<(), Error> {
+        let metadata = Some(Metadata {
+            mode: entry.mode.into(),
+            owner: entry.owner.into(),
+            group: entry.group.into(),
+            mtime: entry.mtime.into(),
+        });
+        if entry.kind == 'd' {
+            let handle = dir.new_directory(entry.name.into(), &mut self.fs)?;
+            return handle.set_metadata(metadata, &mut self.fs);
+        }
+        let linked = entry
+            .inode
+            .and_then(|inode| self.inodes.get(&inode))
+            .copied()
+            .filter(|file| file.view(&self.fs).is_ok());
+        let handle = match (linked, entry.target) {
+            (Some(file), target) => {
+                let handle = dir.new_hard_link(entry.name.into(), file, &mut self.fs)?;
+                if target.is_none() {
+                    // listed again, possibly with another size
+                    dir.new_file(entry.name.into(), entry.size, &mut self.fs)?;
+                }
+                handle
+            },
+            (None, Some(target)) => dir.new_symlink(entry.name.into(), target.into(), &mut self.fs)?,
+            (None, None) => dir.new_file(entry.name.into(), entry.size, &mut self.fs)?,
+        };
+        if let (None, Some(inode)) = (linked, entry.inode) {
+            self.inodes.insert(inode, handle);
+        }
+        handle.set_metadata(metadata, &mut self.fs)
+    }
+
+    /// Apply one line of a transcript without its line ending, `line` being
+    /// its 1-based number and `listing` the directory receiving the entries
+    /// of the `ls` in progress
+    pub(crate) fn apply_line(
+        &mut self,
+        text: &str,
+        line: usize,
+        listing: &mut Option<DirectoryHandle>,
+    ) -> Result<(), Error> {
+        let content = text.trim_start();
+        if content.is_empty() {
+            return Ok(());
+        }
+        // the nom parsers expect every line to be terminated
+        let input = format!("{}\n", content);
+        // `rest` is the input left where parsing stopped
+        let malformed = |rest: &str| Error::Malformed {
+            line,
+            column: text.len() - content.len() + input.len() - rest.len() + 1,
+            text: text.into(),
+        };
+        let stopped = |error: nom::Err<nom::error::Error<&str>>| match error {
+            nom::Err::Error(error) | nom::Err::Failure(error) => malformed(error.input),
+            nom::Err::Incomplete(_) => unreachable!("complete parsers never ask for more input"),
+        };
+        if content.starts_with('$') {
+            *listing = None;
+            let operation = match parse_cmd(&input) {
+                Ok(("", operation)) => operation,
+                Ok((rest, _)) => return Err(malformed(rest)),
+                Err(error) => return Err(stopped(error)),
+            };
+            if let ShellOperation::List(op) = &operation {
+                *listing = Some(self.list_target(op)?);
+            }
+            self.apply(operation)?;
+        } else {
+            let dir = listing.ok_or_else(|| malformed(&input))?;
+            let entry = match list_entry(&input) {
+                Ok(("", entry)) => entry,
+                Ok((rest, _)) => return Err(malformed(rest)),
+                Err(error) => return Err(stopped(error)),
+            };
+            self.add_entry(dir, entry)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, operation: ShellOperation) -> Result<(), Error> {
         match operation {
             ShellOperation::ChangeDirectory(op) => {
-                if op.path == ".." {
-                    state.cwd = state.cwd.parent(&mut state.fs);
-                } else if op.path == "/" {
-                    state.cwd = state.fs.root();
-                } else {
-                    state.cwd = state.cwd.new_directory(op.path.into(), &mut state.fs).unwrap();
-                }
+                self.cwd = self.resolve_directory(&op.path)?;
             },
             ShellOperation::List(op) => {
-                op.entries.iter().for_each(|entry| {
-                    match entry {
-                        ListEntry::File(file) => {
-                            let _ = state.cwd.new_file(file.name.into(), file.size, &mut state.fs);
-                        },
-                        ListEntry::Directory(directory) => {
-                            let _ = state.cwd.new_directory(directory.name.into(), &mut state.fs);
-                        },
-                    };
-                });
+                let dir = self.list_target(&op)?;
+                for entry in op.entries {
+                    self.add_entry(dir, entry)?;
+                }
+            },
+            ShellOperation::MakeDirectory(op) => {
+                let (mut parent, name) = self.resolve_parent(&op.path)?;
+                parent.new_directory(name.into(), &mut self.fs)?;
+            },
+            ShellOperation::Touch(op) => {
+                let (mut parent, name) = self.resolve_parent(&op.path)?;
+                parent.new_file(name.into(), op.size, &mut self.fs)?;
+            },
+            ShellOperation::Remove(op) => {
+                match self.fs.lookup_link(self.cwd, &op.path)? {
+                    Entry::File(file) => self.fs.remove_file(file.handle)?,
+                    Entry::Directory(directory) => {
+                        let handle = directory.handle;
+                        if self.cwd.abspath(&self.fs).starts_with(&handle.abspath(&self.fs)) {
+                            self.cwd = handle.parent(&self.fs);
+                        }
+                        self.fs.remove_directory(handle)?
+                    },
+                };
+            },
+            ShellOperation::Move(op) => {
+                let (dest, name) = match self.fs.lookup(self.cwd, &op.dest) {
+                    Ok(Entry::Directory(directory)) => (directory.handle, None),
+                    Ok(Entry::File(file)) => return Err(Error::NameConflict(file.handle.abspath(&self.fs))),
+                    Err(LookupError::NotFound(_)) => {
+                        let (dest, name) = self.resolve_parent(&op.dest)?;
+                        (dest, Some(name.to_string()))
+                    },
+                    Err(error) => return Err(Error::Lookup(error)),
+                };
+                match self.fs.lookup_link(self.cwd, &op.source)? {
+                    Entry::File(file) => {
+                        let (handle, name) = (file.handle, name.unwrap_or_else(|| file.name.clone()));
+                        self.fs.move_file(handle, dest, name)?;
+                    },
+                    Entry::Directory(directory) => {
+                        let (handle, name) = (directory.handle, name.unwrap_or_else(|| directory.name.clone()));
+                        self.fs.move_directory(handle, dest, name)?;
+                    },
+                };
             },
         }
-        state
-    };
-    match fold_many0(parse_cmd, ShellState::new, fold_fn)(input.as_str()) {
-        Ok((_, state)) => Ok(state),
-        Err(error) => Err(error.to_string()),
+        Ok(())
+    }
+}
+
+/// Incremental transcript parser over any [BufRead].
+///
+/// Every line is applied to the [ShellState] as soon as it is read, so the
+/// stream can be advanced a few lines at a time (it is an [Iterator] over
+/// the numbers of the lines it applied), inspected through
+/// [ShellStream::state] and resumed, without holding the whole transcript
+/// in memory.
+pub struct ShellStream<R> {
+    reader: R,
+    state: ShellState,
+    line: usize,
+    /// Directory receiving the entries of the `ls` in progress
+    listing: Option<DirectoryHandle>,
+    buffer: String,
+    failed: bool,
+}
+
+impl<R: BufRead> ShellStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_policy(reader, ConflictPolicy::default())
+    }
+
+    pub fn with_policy(reader: R, conflict_policy: ConflictPolicy) -> Self {
+        ShellStream {
+            reader,
+            state: ShellState::new(conflict_policy),
+            line: 0,
+            listing: None,
+            buffer: String::new(),
+            failed: false,
+        }
+    }
+
+    /// Reject the lines that would grow the filesystem above `capacity`
+    /// bytes with [Error::DiskFull]
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.state.fs.set_capacity(Some(capacity));
+        self
+    }
+
+    pub fn state(&self) -> &ShellState {
+        &self.state
+    }
+
+    /// Number of lines read so far
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Read and apply the next line, returns `false` at the end of the input
+    pub fn step(&mut self) -> Result<bool, Error> {
+        self.buffer.clear();
+        let read = self.reader.read_line(&mut self.buffer).map_err(|error| Error::Read {
+            line: self.line + 1,
+            kind: error.kind(),
+        })?;
+        if read == 0 {
+            return Ok(false);
+        }
+        self.line += 1;
+        let text = self.buffer.trim_end_matches(['\r', '\n']);
+        self.state.apply_line(text, self.line, &mut self.listing)?;
+        Ok(true)
+    }
+
+    /// Apply the rest of the input and return the final state
+    pub fn finish(mut self) -> Result<ShellState, Error> {
+        while self.step()? {}
+        Ok(self.state)
+    }
+}
+
+impl<R: BufRead> Iterator for ShellStream<R> {
+    type Item = Result<usize, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.step() {
+            Ok(true) => Some(Ok(self.line)),
+            Ok(false) => None,
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            }
+        }
     }
 }
 
+pub fn parse(input: String) -> Result<ShellState, Error> {
+    parse_with_policy(input, ConflictPolicy::default())
+}
+
+/// Like [parse], resolving files that are listed with different sizes
+/// according to `conflict_policy`
+pub fn parse_with_policy(input: String, conflict_policy: ConflictPolicy) -> Result<ShellState, Error> {
+    ShellStream::with_policy(input.as_bytes(), conflict_policy).finish()
+}
+
+/// One line of the indented tree notation, `size` is `None` for directories
+#[derive(PartialEq, Eq, Debug)]
+struct TreeLine<'a> {
+    indent: usize,
+    name: &'a str,
+    size: Option<usize>,
+}
+
+fn tree_file(input: &str) -> IResult<&str, Option<usize>> {
+    map(preceded(tag("file, size="), size), Some)(input)
+}
+
+fn tree_directory(input: &str) -> IResult<&str, Option<usize>> {
+    // a cumulative size is derived from the files, so it is accepted but ignored
+    map(terminated(tag("dir"), opt(preceded(tag(", size="), digit1))), |_| None)(input)
+}
+
+fn tree_line(input: &str) -> IResult<&str, TreeLine<'_>> {
+    let (input, indent) = space0(input)?;
+    let (input, _) = tag("- ")(input)?;
+    let (input, name) = verify(take_until(" ("), |name: &str| !name.is_empty())(input)?;
+    let (input, size) = delimited(tag(" ("), alt((tree_file, tree_directory)), tag(")"))(input)?;
+    let (input, _) = eof(input)?;
+    Ok((input, TreeLine { indent: indent.len(), name, size }))
+}
+
+/// Directory on the current branch of the tree being parsed
+struct TreeFrame {
+    indent: usize,
+    handle: DirectoryHandle,
+    child_indent: Option<usize>,
+}
+
+/// Build a [BasicFileSystem] from the indented tree notation produced by
+/// [crate::render::TreeView]. The first line must be the root directory, the
+/// children of a directory must all be indented by the same amount, and
+/// deeper than their parent.
+pub fn parse_tree(input: String) -> Result<BasicFileSystem, Error> {
+    let mut fs = BasicFileSystem::new();
+    let mut stack: Vec<TreeFrame> = vec![];
+    let mut seen_root = false;
+    for (index, text) in input.lines().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let bad_indentation = || Error::BadIndentation { line: index + 1, text: text.into() };
+        let line = match tree_line(text) {
+            Ok((_, line)) => line,
+            Err(nom::Err::Error(error)) | Err(nom::Err::Failure(error)) => {
+                return Err(Error::Malformed {
+                    line: index + 1,
+                    column: text.len() - error.input.len() + 1,
+                    text: text.into(),
+                })
+            }
+            Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers never ask for more input"),
+        };
+        if !seen_root {
+            if line.name != fs.sep() || line.size.is_some() || line.indent != 0 {
+                return Err(bad_indentation());
+            }
+            stack.push(TreeFrame { indent: 0, handle: fs.root(), child_indent: None });
+            seen_root = true;
+            continue;
+        }
+        while stack.last().is_some_and(|frame| frame.indent >= line.indent) {
+            stack.pop();
+        }
+        let parent = stack.last_mut().ok_or_else(bad_indentation)?;
+        if *parent.child_indent.get_or_insert(line.indent) != line.indent {
+            return Err(bad_indentation());
+        }
+        let mut handle = parent.handle;
+        match line.size {
+            Some(size) => {
+                handle.new_file(line.name.into(), size, &mut fs)?;
+            },
+            None => {
+                let handle = handle.new_directory(line.name.into(), &mut fs)?;
+                stack.push(TreeFrame { indent: line.indent, handle, child_indent: None });
+            },
+        }
+    }
+    Ok(fs)
+}
+
 #[cfg(test)]
 mod unittest {
 
@@ -139,19 +652,21 @@ mod unittest {
     use crate::fs::{
         FileHandle,
         File,
+        FileKind,
         Directory,
         Path,
+        Tree,
     };
 
     #[test]
     fn parse_cd() {
         assert_eq!(
             parse_cmd("$ cd /\n"),
-            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "/" }))));
+            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "/".into() }))));
 
         assert_eq!(
             parse_cmd("$ cd abc\n"),
-            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "abc" }))));
+            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "abc".into() }))));
 
         assert!(parse_cmd("$ cd\n").is_err());
     }
@@ -160,7 +675,7 @@ mod unittest {
     fn parse_ls() {
         assert_eq!(
             parse_cmd("\n$ ls\n"),
-            Ok(("", ShellOperation::List(ListOperation { entries: [].into() }))));
+            Ok(("", ShellOperation::List(ListOperation { path: None, entries: [].into() }))));
 
         let input =
 r#"
@@ -172,6 +687,7 @@ dir fhhwv
         assert_eq!(
             parse_cmd(input),
             Ok(("", ShellOperation::List(ListOperation {
+                path: None,
                 entries: [
                     ListEntry::Directory(DirectoryEntry { name: "bzgf" }),
                     ListEntry::File(FileEntry { name: "dngdnvv.qdf", size: 199775 }),
@@ -228,71 +744,390 @@ $ ls
         assert!(result.is_ok());
 
         let state = result.unwrap();
-        assert_eq!(state.cwd.view(&state.fs).name, "d");
+        assert_eq!(state.cwd.view(&state.fs).unwrap().name, "d");
         assert_eq!(state.cwd.abspath(&state.fs), "/d/");
 
+        let fs_id = state.fs.id();
+
         assert_eq!(state.fs.files, [
-            File { handle: FileHandle { index: 0 }, name: "b.txt".into(), size: 14848514, dir: DirectoryHandle { index: 0 }},
-            File { handle: FileHandle { index: 1 }, name: "c.dat".into(), size: 8504156, dir: DirectoryHandle { index: 0 }},
-            File { handle: FileHandle { index: 2 }, name: "f".into(), size: 29116, dir: DirectoryHandle { index: 1 }},
-            File { handle: FileHandle { index: 3 }, name: "g".into(), size: 2557, dir: DirectoryHandle { index: 1 }},
-            File { handle: FileHandle { index: 4 }, name: "h.lst".into(), size: 62596, dir: DirectoryHandle { index: 1 }},
-            File { handle: FileHandle { index: 5 }, name: "i".into(), size: 584, dir: DirectoryHandle { index: 3 }},
-            File { handle: FileHandle { index: 6 }, name: "j".into(), size: 4060174, dir: DirectoryHandle { index: 2 }},
-            File { handle: FileHandle { index: 7 }, name: "d.log".into(), size: 8033020, dir: DirectoryHandle { index: 2 }},
-            File { handle: FileHandle { index: 8 }, name: "d.ext".into(), size: 5626152, dir: DirectoryHandle { index: 2 }},
-            File { handle: FileHandle { index: 9 }, name: "k".into(), size: 7214296, dir: DirectoryHandle { index: 2 }},
+            Some(File { handle: FileHandle { index: 0, generation: 2, fs_id }, name: "b.txt".into(), size: 14848514, dir: DirectoryHandle { index: 0, generation: 0, fs_id }, kind: FileKind::Regular, inode: 2, metadata: None }),
+            Some(File { handle: FileHandle { index: 1, generation: 3, fs_id }, name: "c.dat".into(), size: 8504156, dir: DirectoryHandle { index: 0, generation: 0, fs_id }, kind: FileKind::Regular, inode: 3, metadata: None }),
+            Some(File { handle: FileHandle { index: 2, generation: 6, fs_id }, name: "f".into(), size: 29116, dir: DirectoryHandle { index: 1, generation: 1, fs_id }, kind: FileKind::Regular, inode: 6, metadata: None }),
+            Some(File { handle: FileHandle { index: 3, generation: 7, fs_id }, name: "g".into(), size: 2557, dir: DirectoryHandle { index: 1, generation: 1, fs_id }, kind: FileKind::Regular, inode: 7, metadata: None }),
+            Some(File { handle: FileHandle { index: 4, generation: 8, fs_id }, name: "h.lst".into(), size: 62596, dir: DirectoryHandle { index: 1, generation: 1, fs_id }, kind: FileKind::Regular, inode: 8, metadata: None }),
+            Some(File { handle: FileHandle { index: 5, generation: 9, fs_id }, name: "i".into(), size: 584, dir: DirectoryHandle { index: 3, generation: 5, fs_id }, kind: FileKind::Regular, inode: 9, metadata: None }),
+            Some(File { handle: FileHandle { index: 6, generation: 10, fs_id }, name: "j".into(), size: 4060174, dir: DirectoryHandle { index: 2, generation: 4, fs_id }, kind: FileKind::Regular, inode: 10, metadata: None }),
+            Some(File { handle: FileHandle { index: 7, generation: 11, fs_id }, name: "d.log".into(), size: 8033020, dir: DirectoryHandle { index: 2, generation: 4, fs_id }, kind: FileKind::Regular, inode: 11, metadata: None }),
+            Some(File { handle: FileHandle { index: 8, generation: 12, fs_id }, name: "d.ext".into(), size: 5626152, dir: DirectoryHandle { index: 2, generation: 4, fs_id }, kind: FileKind::Regular, inode: 12, metadata: None }),
+            Some(File { handle: FileHandle { index: 9, generation: 13, fs_id }, name: "k".into(), size: 7214296, dir: DirectoryHandle { index: 2, generation: 4, fs_id }, kind: FileKind::Regular, inode: 13, metadata: None }),
         ]);
         assert_eq!(state.fs.dirs, [
-            Directory {
-                handle: DirectoryHandle { index: 0 },
+            Some(Directory {
+                handle: DirectoryHandle { index: 0, generation: 0, fs_id },
                 name: "/".into(),
-                parent: DirectoryHandle { index: 0 },
+                parent: DirectoryHandle { index: 0, generation: 0, fs_id },
                 dirs: [
-                    DirectoryHandle { index: 1 },
-                    DirectoryHandle { index: 2 }
+                    DirectoryHandle { index: 1, generation: 1, fs_id },
+                    DirectoryHandle { index: 2, generation: 4, fs_id }
                 ].into(),
                 files: [
-                    FileHandle { index: 0 },
-                    FileHandle { index: 1 },
+                    FileHandle { index: 0, generation: 2, fs_id },
+                    FileHandle { index: 1, generation: 3, fs_id },
                 ].into(),
-            },
-            Directory {
-                handle: DirectoryHandle { index: 1 },
+                size: 48381165,
+                metadata: None,
+                quota: None,
+            }),
+            Some(Directory {
+                handle: DirectoryHandle { index: 1, generation: 1, fs_id },
                 name: "a".into(),
-                parent: DirectoryHandle { index: 0 },
+                parent: DirectoryHandle { index: 0, generation: 0, fs_id },
                 dirs: [
-                    DirectoryHandle { index: 3 },
+                    DirectoryHandle { index: 3, generation: 5, fs_id },
                 ].into(),
                 files: [
-                    FileHandle { index: 2 },
-                    FileHandle { index: 3 },
-                    FileHandle { index: 4 },
+                    FileHandle { index: 2, generation: 6, fs_id },
+                    FileHandle { index: 3, generation: 7, fs_id },
+                    FileHandle { index: 4, generation: 8, fs_id },
                 ].into(),
-            },
-            Directory {
-                handle: DirectoryHandle { index: 2 },
+                size: 94853,
+                metadata: None,
+                quota: None,
+            }),
+            Some(Directory {
+                handle: DirectoryHandle { index: 2, generation: 4, fs_id },
                 name: "d".into(),
-                parent: DirectoryHandle { index: 0 },
+                parent: DirectoryHandle { index: 0, generation: 0, fs_id },
                 dirs: [].into(),
                 files: [
-                    FileHandle { index: 6 },
-                    FileHandle { index: 7 },
-                    FileHandle { index: 8 },
-                    FileHandle { index: 9 },
+                    FileHandle { index: 6, generation: 10, fs_id },
+                    FileHandle { index: 7, generation: 11, fs_id },
+                    FileHandle { index: 8, generation: 12, fs_id },
+                    FileHandle { index: 9, generation: 13, fs_id },
                 ].into(),
-            },
-            Directory {
-                handle: DirectoryHandle { index: 3 },
+                size: 24933642,
+                metadata: None,
+                quota: None,
+            }),
+            Some(Directory {
+                handle: DirectoryHandle { index: 3, generation: 5, fs_id },
                 name: "e".into(),
-                parent: DirectoryHandle { index: 1 },
+                parent: DirectoryHandle { index: 1, generation: 1, fs_id },
                 dirs: [].into(),
                 files: [
-                    FileHandle { index: 5 },
+                    FileHandle { index: 5, generation: 9, fs_id },
                 ].into(),
-            },
+                size: 584,
+                metadata: None,
+                quota: None,
+            }),
         ]);
     }
 
-}
+    #[test]
+    fn parse_errors() {
+        let input = "$ cd /\n$ ls\ndir a\n$ cd a\n$ cd b\n";
+        assert_eq!(parse(input.into()).unwrap_err(), Error::UnknownDirectory("/a/b".into()));
+
+        let input = format!("$ cd /\n$ ls\n{} a\n1 b\n", usize::MAX);
+        assert_eq!(parse(input).unwrap_err(), Error::SizeOverflow("/".into()));
+
+        let input = "$ cd /\n$ ls\n42 f\n$ cd f\n";
+        assert_eq!(
+            parse(input.into()).unwrap_err(),
+            Error::Lookup(LookupError::NotADirectory("/f".into())));
+
+        assert_eq!(parse("$ cd /\n$ cd ..\n".into()).unwrap_err(), Error::AboveRoot);
+
+        let input = "$ cd /\n$ ls\ndir a\n  $ pwd\n";
+        assert_eq!(parse(input.into()).unwrap_err(), Error::Malformed {
+            line: 4,
+            column: 5,
+            text: "  $ pwd".into(),
+        });
+        assert_eq!(parse("$ cd a b\n".into()).unwrap_err(), Error::Malformed {
+            line: 1,
+            column: 7,
+            text: "$ cd a b".into(),
+        });
+        assert_eq!(parse("$ ls\n  99999999999999999999999 a\n".into()).unwrap_err(), Error::Malformed {
+            line: 2,
+            column: 3,
+            text: "  99999999999999999999999 a".into(),
+        });
+        assert!(matches!(parse("$ touch 99999999999999999999999 a\n".into()), Err(Error::Malformed { column: 9, .. })));
+
+        let input = "$ ls\ndir a\n12 a\n";
+        assert_eq!(parse(input.into()).unwrap_err(), Error::NameConflict("/a".into()));
+
+        let input = "$ ls\n12 a\n$ ls\n13 a\n";
+        assert!(parse(input.into()).is_ok());
+        let state = parse_with_policy(input.into(), ConflictPolicy::KeepLast).unwrap();
+        assert_eq!(state.fs.root().size(&state.fs), 13);
+        assert_eq!(
+            parse_with_policy(input.into(), ConflictPolicy::Reject).unwrap_err(),
+            Error::SizeConflict { path: "/a".into(), listed: 12, size: 13 });
+
+        let input = "$ ls\n12 a\nxyz b\n";
+        assert_eq!(parse(input.into()).unwrap_err(), Error::Malformed {
+            line: 3,
+            column: 1,
+            text: "xyz b".into(),
+        });
+    }
+
+    #[test]
+    fn parse_tree_example() {
+        // the layout of the tree in `parse_example`
+        let input =
+r#"
+- / (dir)
+ - a (dir)
+   - e (dir)
+     - i (file, size=584)
+   - f (file, size=29116)
+   - g (file, size=2557)
+   - h.lst (file, size=62596)
+ - b.txt (file, size=14848514)
+ - c.dat (file, size=8504156)
+ - d (dir, size=24933642)
+   - j (file, size=4060174)
+   - d.log (file, size=8033020)
+   - d.ext (file, size=5626152)
+   - k (file, size=7214296)
+"#;
+        let fs = parse_tree(input.into()).unwrap();
+        assert_eq!(fs.root().size(&fs), 48381165);
+        assert_eq!(fs.dir_sizes().len(), 4);
+
+        // round trip through the rendered tree
+        let rendered = fs.to_string();
+        assert_eq!(parse_tree(rendered.clone()).unwrap().to_string(), rendered);
+    }
+
+    #[test]
+    fn parse_tree_errors() {
+        assert_eq!(
+            parse_tree("  - / (dir)\n".into()).unwrap_err(),
+            Error::BadIndentation { line: 1, text: "  - / (dir)".into() });
+        assert_eq!(
+            parse_tree("- / (dir)\n- a (dir)\n".into()).unwrap_err(),
+            Error::BadIndentation { line: 2, text: "- a (dir)".into() });
+        assert_eq!(
+            parse_tree("- / (dir)\n    - a (dir)\n  - b (dir)\n".into()).unwrap_err(),
+            Error::BadIndentation { line: 3, text: "  - b (dir)".into() });
+        assert_eq!(
+            parse_tree("- / (dir)\n  - a (file, size=1)\n    - b (dir)\n".into()).unwrap_err(),
+            Error::BadIndentation { line: 3, text: "    - b (dir)".into() });
+        assert_eq!(
+            parse_tree("- / (dir)\n  - a (link)\n".into()).unwrap_err(),
+            Error::Malformed { line: 2, column: 8, text: "  - a (link)".into() });
+        assert!(matches!(
+            parse_tree("- / (dir)\n  - a (file, size=99999999999999999999999)\n".into()),
+            Err(Error::Malformed { line: 2, .. })));
+    }
 
+    #[test]
+    fn parse_extended_cmds() {
+        assert_eq!(
+            parse_cmd("$ cd /a/b-c/../d_e.f\n"),
+            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "/a/b-c/../d_e.f".into() }))));
+        assert_eq!(
+            parse_cmd("$ ls a/b\n12 c\n"),
+            Ok(("", ShellOperation::List(ListOperation {
+                path: Some("a/b".into()),
+                entries: [ListEntry::File(FileEntry { name: "c", size: 12 })].into(),
+            }))));
+        assert_eq!(
+            parse_cmd("$ mkdir a.b\n"),
+            Ok(("", ShellOperation::MakeDirectory(MakeDirectoryOperation { path: "a.b".into() }))));
+        assert_eq!(
+            parse_cmd("$ touch 42 a/b.txt\n"),
+            Ok(("", ShellOperation::Touch(TouchOperation { size: 42, path: "a/b.txt".into() }))));
+        assert_eq!(
+            parse_cmd("$ rm ../x\n"),
+            Ok(("", ShellOperation::Remove(RemoveOperation { path: "../x".into() }))));
+        assert_eq!(
+            parse_cmd("$ mv a /b/c\n"),
+            Ok(("", ShellOperation::Move(MoveOperation { source: "a".into(), dest: "/b/c".into() }))));
+
+        assert!(parse_cmd("$ touch a.txt\n").is_err());
+        assert!(parse_cmd("$ mv a\n").is_err());
+    }
+
+    /// Paths with whitespace or quotes are quoted as in a shell
+    #[test]
+    fn parse_quoted_paths() {
+        assert_eq!(
+            parse_cmd("$ cd 'my docs'/it\\'s\n"),
+            Ok(("", ShellOperation::ChangeDirectory(ChangeDirectoryOperation { path: "my docs/it's".into() }))));
+        assert_eq!(
+            parse_cmd("$ mv a\\ b ''c'\\d'\n"),
+            Ok(("", ShellOperation::Move(MoveOperation { source: "a b".into(), dest: "c\\d".into() }))));
+        assert!(parse_cmd("$ cd 'a b\n").is_err());
+        assert!(parse_cmd("$ rm a\\\n").is_err());
+
+        let state = parse("$ cd /\n$ ls\ndir a b\n$ cd 'a b'\n$ touch 3 \"c\"\\ d\n$ mv '\"c\" d' \\'e\n".into()).unwrap();
+        assert_eq!(state.fs.lookup(state.fs.root(), "/a b/'e").map(|entry| entry.size()), Ok(3));
+        assert_eq!(state.cwd.abspath(&state.fs), "/a b/");
+    }
+
+    #[test]
+    fn parse_long_listing() {
+        assert_eq!(
+            parse_cmd("$ ls -l -i a\n42 lrwxrwxrwx 1 alice staff 8 2022-12-07 10:00 b c -> ../d e\n"),
+            Ok(("", ShellOperation::List(ListOperation {
+                path: Some("a".into()),
+                entries: [ListEntry::Long(LongEntry {
+                    inode: Some(42),
+                    kind: 'l',
+                    mode: "rwxrwxrwx",
+                    owner: "alice",
+                    group: "staff",
+                    size: 8,
+                    mtime: "2022-12-07 10:00",
+                    name: "b c",
+                    target: Some("../d e"),
+                })].into(),
+            }))));
+        // the `total` line is skipped, an entry called `total` is not
+        assert_eq!(
+            parse_cmd("$ ls -l\ntotal 0\n5 total\n"),
+            Ok(("", ShellOperation::List(ListOperation {
+                path: None,
+                entries: [ListEntry::Total, ListEntry::File(FileEntry { name: "total", size: 5 })].into(),
+            }))));
+
+        // short entries that merely look like long ones
+        assert_eq!(
+            parse_cmd("$ ls\n12 d.log\n"),
+            Ok(("", ShellOperation::List(ListOperation {
+                path: None,
+                entries: [ListEntry::File(FileEntry { name: "d.log", size: 12 })].into(),
+            }))));
+
+        let input = r#"
+$ cd /
+$ ls -li
+total 12
+ 100 drwxr-xr-x 2 alice staff 4096 2022-12-07 10:00 a
+ 200 -rw-r--r-- 2 alice staff 1000 2022-12-07 10:01 b.txt
+ 300 lrwxrwxrwx 1 alice staff 3 2022-12-07 10:02 c -> a/e
+$ cd a
+$ ls -li
+total 8
+ 200 -rw-r--r-- 2 alice staff 1000 2022-12-07 10:01 e
+ 400 -rw------- 1 bob staff 24 2022-12-07 10:03 f
+$ ls
+12 g
+"#;
+        let mut state = parse(input.into()).unwrap();
+        let fs = &state.fs;
+        let (b, c) = match (fs.lookup(fs.root(), "/b.txt"), fs.lookup_link(fs.root(), "/c")) {
+            (Ok(Entry::File(b)), Ok(Entry::File(c))) => (b, c),
+            _ => panic!("{}", fs),
+        };
+        assert_eq!(b.handle.links(fs).len(), 2);
+        assert_eq!(c.kind, FileKind::Symlink("a/e".into()));
+        assert_eq!(fs.lookup(fs.root(), "/c").map(|entry| entry.size()), Ok(1000));
+        assert_eq!(fs.root().size(fs), 1000 + 3 + 24 + 12);
+        assert_eq!(state.cwd.size(fs), 1000 + 24 + 12);
+
+        let metadata = b.metadata.as_ref().unwrap();
+        assert_eq!((metadata.mode.as_str(), metadata.owner.as_str()), ("rw-r--r--", "alice"));
+        assert_eq!(metadata.mtime, "2022-12-07 10:01");
+        assert_eq!(state.cwd.view(fs).unwrap().metadata.as_ref().map(|metadata| metadata.group.as_str()), Some("staff"));
+        assert!(fs.lookup(state.cwd, "g").is_ok_and(|entry| matches!(entry, Entry::File(g) if g.metadata.is_none())));
+
+        // a file cannot take the place of a link, even with the same size
+        assert_eq!(state.apply(parse_cmd("$ touch 3 /c\n").unwrap().1), Err(Error::NameConflict("/c".into())));
+        let listed = format!("{}$ cd /\n$ ls\n3 c\n", input);
+        assert_eq!(parse(listed).unwrap_err(), Error::NameConflict("/c".into()));
+
+        // `rm` removes the link itself, the file keeps its other name
+        state.apply(parse_cmd("$ rm /c\n").unwrap().1).unwrap();
+        state.apply(parse_cmd("$ rm /b.txt\n").unwrap().1).unwrap();
+        assert_eq!(state.fs.root().size(&state.fs), 1000 + 24 + 12);
+        assert_eq!(state.fs.lookup(state.fs.root(), "/a/e").map(|entry| entry.size()), Ok(1000));
+    }
+
+    #[test]
+    fn parse_extended_session() {
+        let input =
+r#"
+$ mkdir src
+$ mkdir src/my-lib_v1.0
+$ cd src/my-lib_v1.0
+$ touch 100 lib.rs
+$ ls /
+dir docs
+10 README.md
+$ cd ../../docs
+$ touch 20 guide.md
+$ ls /src
+dir my-lib_v1.0
+5 main.rs
+$ mv /src/main.rs ../src/my-lib_v1.0/main-renamed.rs
+$ mv /README.md /docs
+$ cd /src/my-lib_v1.0
+$ rm /docs
+"#;
+        let state = parse(input.into()).unwrap();
+        assert_eq!(state.fs.to_string(),
+r#"- / (dir)
+  - src (dir)
+    - my-lib_v1.0 (dir)
+      - lib.rs (file, size=100)
+      - main-renamed.rs (file, size=5)
+"#);
+        assert_eq!(state.cwd.abspath(&state.fs), "/src/my-lib_v1.0/");
+        assert_eq!(state.fs.root().size(&state.fs), 105);
+
+        // removing the current directory moves back to its parent
+        let state = parse("$ mkdir a\n$ mkdir a/b\n$ cd a/b\n$ rm /a\n".into()).unwrap();
+        assert_eq!(state.cwd, state.fs.root());
+
+        assert_eq!(
+            parse("$ mkdir a\n$ touch 1 b\n$ touch 2 a/b\n$ mv b a/\n".into()).unwrap_err(),
+            Error::NameConflict("/a/b".into()));
+        assert_eq!(parse("$ mkdir a\n$ cd a/../..\n".into()).unwrap_err(), Error::AboveRoot);
+        assert_eq!(parse("$ rm /\n".into()).unwrap_err(), Error::RootDirectory);
+    }
+
+    /// The stream can be paused after any line, inspected and resumed
+    #[test]
+    fn parse_stream() {
+        let input = "$ cd /\n$ ls\ndir a\n10 b\n\n$ cd a\n$ ls\n20 c\n";
+        let mut stream = ShellStream::new(input.as_bytes());
+        assert_eq!(stream.by_ref().take(3).collect::<Result<Vec<_>, _>>(), Ok(vec![1, 2, 3]));
+        assert_eq!(stream.state().fs.root().size(&stream.state().fs), 0);
+        assert_eq!(stream.state().fs.dir_sizes().len(), 2);
+
+        assert_eq!(stream.step(), Ok(true));
+        assert_eq!(stream.line(), 4);
+        assert_eq!(stream.state().fs.root().size(&stream.state().fs), 10);
+
+        while stream.state().cwd == stream.state().fs.root() {
+            assert_eq!(stream.step(), Ok(true));
+        }
+        assert_eq!(stream.line(), 6);
+        assert_eq!(stream.state().cwd.abspath(&stream.state().fs), "/a/");
+
+        let state = stream.finish().unwrap();
+        assert_eq!(state.fs.root().size(&state.fs), 30);
+
+        // errors end the iteration
+        let mut stream = ShellStream::new("$ cd /\n12 a\n$ ls\n".as_bytes());
+        assert_eq!(stream.next(), Some(Ok(1)));
+        assert_eq!(stream.next(), Some(Err(Error::Malformed { line: 2, column: 1, text: "12 a".into() })));
+        assert_eq!(stream.next(), None);
+
+        // so do lines that cannot be read
+        let mut stream = ShellStream::new(&b"$ cd /\n\xff a\n$ ls\n"[..]);
+        assert_eq!(stream.next(), Some(Ok(1)));
+        let error = stream.next().unwrap().unwrap_err();
+        assert_eq!(error, Error::Read { line: 2, kind: std::io::ErrorKind::InvalidData });
+        assert_eq!(error.to_string(), "2: cannot read line: invalid data");
+        assert_eq!(stream.next(), None);
+    }
+}
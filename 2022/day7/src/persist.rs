@@ -0,0 +1,224 @@
+//! Saving and loading filesystems, available with the `serde` feature.
+//!
+//! A [BasicFileSystem] is stored as its arena together with a schema
+//! version, either as JSON or as a compact binary snapshot. Loading gives the
+//! filesystem a new id, so that handles of the saved filesystem do not mix
+//! with the loaded one, and rejects it if [crate::fsck::check] finds any
+//! violation.
+
+use std::collections::HashMap;
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::cow::CowVec;
+use crate::error::Error;
+use crate::fs::{BasicFileSystem, ConflictPolicy, Directory, DirectoryHandle, File};
+use crate::parse::ShellState;
+
+/// Version of the schema written by this crate
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// Start of every binary snapshot
+const MAGIC: &[u8; 4] = b"DAY7";
+
+#[derive(Serialize)]
+struct SchemaRef<'a> {
+    version: u32,
+    conflict_policy: ConflictPolicy,
+    capacity: Option<usize>,
+    generation: usize,
+    dirs: &'a CowVec<Option<Directory>>,
+    files: &'a CowVec<Option<File>>,
+}
+
+#[derive(Deserialize)]
+struct Schema {
+    version: u32,
+    conflict_policy: ConflictPolicy,
+    capacity: Option<usize>,
+    generation: usize,
+    dirs: Vec<Option<Directory>>,
+    files: Vec<Option<File>>,
+}
+
+/// Only the version, to reject other schemas before decoding the rest
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+impl Serialize for BasicFileSystem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SchemaRef {
+            version: SCHEMA_VERSION,
+            conflict_policy: self.conflict_policy(),
+            capacity: self.capacity(),
+            generation: self.generation(),
+            dirs: &self.dirs,
+            files: &self.files,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BasicFileSystem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let schema = Schema::deserialize(deserializer)?;
+        if schema.version != SCHEMA_VERSION {
+            return Err(D::Error::custom(Error::UnsupportedVersion(schema.version)));
+        }
+        let mut fs = BasicFileSystem::from_arena(schema.dirs, schema.files, schema.generation, schema.conflict_policy);
+        fs.set_capacity(schema.capacity);
+        match fs.fsck().violations.first() {
+            Some(violation) => Err(D::Error::custom(violation)),
+            None => Ok(fs),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ShellStateRef<'a> {
+    fs: &'a BasicFileSystem,
+    cwd: DirectoryHandle,
+}
+
+#[derive(Deserialize)]
+struct ShellStateSchema {
+    fs: BasicFileSystem,
+    cwd: DirectoryHandle,
+}
+
+impl Serialize for ShellState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ShellStateRef { fs: &self.fs, cwd: self.cwd }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ShellState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ShellStateSchema { fs, mut cwd } = ShellStateSchema::deserialize(deserializer)?;
+        cwd.fs_id = fs.id();
+        cwd.view(&fs).map_err(|_| D::Error::custom("bad current directory"))?;
+        // inode numbers only matter while a transcript is being parsed
+        Ok(ShellState { fs, cwd, inodes: HashMap::new() })
+    }
+}
+
+fn bad_snapshot(error: impl std::fmt::Display) -> Error {
+    Error::BadSnapshot(error.to_string())
+}
+
+impl BasicFileSystem {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let Version { version } = serde_json::from_str(json).map_err(bad_snapshot)?;
+        if version != SCHEMA_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        serde_json::from_str(json).map_err(bad_snapshot)
+    }
+
+    /// Compact binary snapshot, much faster to load than re-parsing a transcript
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self).unwrap();
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let bytes = bytes.strip_prefix(MAGIC).ok_or_else(|| bad_snapshot("not a day7 snapshot"))?;
+        // the version is the first field of the schema
+        let version: u32 = bincode::deserialize(bytes).map_err(bad_snapshot)?;
+        if version != SCHEMA_VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        bincode::deserialize(bytes).map_err(bad_snapshot)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::{example_state, EXAMPLE};
+    use crate::fs::{Path, Tree};
+    use crate::parse::parse;
+
+    #[test]
+    fn round_trip() {
+        let mut state = parse(format!("{}$ rm /a/e\n$ mkdir /x\n", EXAMPLE)).unwrap();
+        for fs in [
+            BasicFileSystem::from_json(&state.fs.to_json()).unwrap(),
+            BasicFileSystem::from_bytes(&state.fs.to_bytes()).unwrap(),
+        ] {
+            assert_ne!(fs.id(), state.fs.id());
+            assert_eq!(fs.to_string(), state.fs.to_string());
+            assert_eq!(fs.root().size(&fs), 48381165 - 584);
+            assert_eq!(fs.conflict_policy(), state.fs.conflict_policy());
+
+            // tombstones are reused after loading, with newer generations
+            let mut fs = fs;
+            let mut root = fs.root();
+            let y = root.new_directory("y".into(), &mut fs).unwrap();
+            assert!(y.index < fs.dirs.len() && y.generation >= fs.dirs.len());
+        }
+
+        // hard links are recognised again after loading
+        let mut d = state.resolve_directory("/d").unwrap();
+        let b = state.fs.files[0].as_ref().unwrap().handle;
+        d.new_hard_link("b2".into(), b, &mut state.fs).unwrap();
+        let fs = BasicFileSystem::from_bytes(&state.fs.to_bytes()).unwrap();
+        assert_eq!(fs.files[0].as_ref().unwrap().handle.links(&fs).len(), 2);
+        assert_eq!(fs.root().size(&fs), state.fs.root().size(&state.fs));
+
+        // so are the capacity and the quotas
+        state.fs.set_capacity(Some(50_000_000));
+        d.set_quota(Some(30_000_000), &mut state.fs).unwrap();
+        let fs = BasicFileSystem::from_json(&state.fs.to_json()).unwrap();
+        assert_eq!(fs.capacity(), Some(50_000_000));
+        assert_eq!(fs.quota_usage().iter().map(|usage| (usage.quota, usage.used)).collect::<Vec<_>>(), [(30_000_000, 39782156)]);
+
+        state.cwd = state.resolve_directory("/a").unwrap();
+        let loaded: ShellState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        assert_eq!(loaded.cwd.abspath(&loaded.fs), "/a/");
+        assert_eq!(loaded.cwd.fs_id, loaded.fs.id());
+    }
+
+    #[test]
+    fn load_errors() {
+        let state = example_state();
+        let json = state.fs.to_json();
+
+        let future = json.replacen("\"version\":4", "\"version\":5", 1);
+        assert_eq!(BasicFileSystem::from_json(&future).unwrap_err(), Error::UnsupportedVersion(5));
+        let mut bytes = state.fs.to_bytes();
+        bytes[4] = 9;
+        assert_eq!(BasicFileSystem::from_bytes(&bytes).unwrap_err(), Error::UnsupportedVersion(9));
+        assert!(matches!(BasicFileSystem::from_bytes(b"DAY6"), Err(Error::BadSnapshot(_))));
+        assert!(matches!(BasicFileSystem::from_bytes(&state.fs.to_bytes()[..40]), Err(Error::BadSnapshot(_))));
+
+        let resized = json.replacen("\"size\":584", "\"size\":585", 1);
+        let error = BasicFileSystem::from_json(&resized).unwrap_err();
+        let reason = "/a/e/: cached size 585 instead of 584";
+        assert!(matches!(error, Error::BadSnapshot(message) if message.starts_with(reason)));
+
+        // `/a/e/` claims to live in `/d/`, which does not list it
+        let mut fs = BasicFileSystem::from_json(&json).unwrap();
+        fs.dirs[3].as_mut().unwrap().parent = fs.dirs[2].as_ref().unwrap().handle;
+        let error = BasicFileSystem::from_json(&fs.to_json()).unwrap_err();
+        let reason = "/d/e/: not listed by its directory";
+        assert!(matches!(error, Error::BadSnapshot(message) if message.starts_with(reason)));
+
+        // names that cannot be looked up
+        for name in ["x/y", ""] {
+            let mut fs = BasicFileSystem::from_json(&json).unwrap();
+            fs.dirs[3].as_mut().unwrap().name = name.into();
+            let error = BasicFileSystem::from_json(&fs.to_json()).unwrap_err();
+            assert_eq!(error, Error::BadSnapshot(format!("/a/{}/: invalid name", name)));
+        }
+    }
+}
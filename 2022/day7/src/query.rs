@@ -0,0 +1,239 @@
+use std::ops::{Bound, RangeBounds};
+
+use crate::fs::{BasicFileSystem, DirectoryHandle, Entry};
+use crate::render::SortOrder;
+
+/// Kind of entry selected by [Query::kind]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Kind {
+    File,
+    Directory,
+}
+
+type Predicate<'a> = Box<dyn Fn(&Entry) -> bool + 'a>;
+
+/// Selects entries of a [BasicFileSystem] without writing a traversal:
+///
+/// ```
+/// # use day7::fs::BasicFileSystem;
+/// # use day7::query::Kind;
+/// # use day7::render::SortOrder;
+/// # let fs = BasicFileSystem::new();
+/// // the 10 largest files
+/// let largest: Vec<_> = fs.query().kind(Kind::File).sort_by(SortOrder::Size).limit(10).iter().collect();
+/// ```
+///
+/// Without [Query::sort_by], entries come in pre-order, files of a directory
+/// before its subdirectories.
+pub struct Query<'a> {
+    fs: &'a BasicFileSystem,
+    under: DirectoryHandle,
+    kind: Option<Kind>,
+    name: Option<String>,
+    size: (Bound<usize>, Bound<usize>),
+    depth: (Bound<usize>, Bound<usize>),
+    filters: Vec<Predicate<'a>>,
+    order: Option<SortOrder>,
+    limit: Option<usize>,
+}
+
+impl<'a> Query<'a> {
+    pub fn new(fs: &'a BasicFileSystem) -> Self {
+        Query {
+            fs,
+            under: fs.root(),
+            kind: None,
+            name: None,
+            size: (Bound::Unbounded, Bound::Unbounded),
+            depth: (Bound::Unbounded, Bound::Unbounded),
+            filters: vec![],
+            order: None,
+            limit: None,
+        }
+    }
+
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only entries whose name matches `pattern`, where `*` matches any
+    /// sequence of characters and `?` matches a single character
+    pub fn name(mut self, pattern: &str) -> Self {
+        self.name = Some(pattern.into());
+        self
+    }
+
+    /// Only entries whose size, cumulative for directories, is in `range`
+    pub fn size(mut self, range: impl RangeBounds<usize>) -> Self {
+        self.size = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Only entries whose depth is in `range`, the directory given to
+    /// [Query::under] being at depth 0 and its entries at depth 1
+    pub fn depth(mut self, range: impl RangeBounds<usize>) -> Self {
+        self.depth = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Only `dir` and the entries below it, instead of the whole filesystem
+    pub fn under(mut self, dir: DirectoryHandle) -> Self {
+        self.under = dir;
+        self
+    }
+
+    /// Only entries for which `predicate` holds, may be given several times
+    pub fn filter(mut self, predicate: impl Fn(&Entry) -> bool + 'a) -> Self {
+        self.filters.push(Box::new(predicate));
+        self
+    }
+
+    pub fn sort_by(mut self, order: SortOrder) -> Self {
+        self.order = Some(order);
+        self
+    }
+
+    /// At most `limit` entries, taken after sorting
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn matches(&self, entry: &Entry, depth: usize) -> bool {
+        let kind = match entry {
+            Entry::File(_) => Kind::File,
+            Entry::Directory(_) => Kind::Directory,
+        };
+        self.kind.is_none_or(|wanted| wanted == kind)
+            && self.name.as_ref().is_none_or(|pattern| glob_match(pattern, entry.name()))
+            && self.size.contains(&entry.size())
+            && self.depth.contains(&depth)
+            && self.filters.iter().all(|predicate| predicate(entry))
+    }
+
+    /// Whether entries below `depth` may still match
+    fn descend(&self, depth: usize) -> bool {
+        match self.depth.1 {
+            Bound::Included(max) => depth < max,
+            Bound::Excluded(max) => depth + 1 < max,
+            Bound::Unbounded => true,
+        }
+    }
+
+    /// Matching entries, lazily unless they have to be sorted
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Entry<'a>> + '_> {
+        let fs = self.fs;
+        let mut pending = vec![(0, Entry::Directory(fs.dir(self.under)))];
+        let entries = std::iter::from_fn(move || {
+            let (depth, entry) = pending.pop()?;
+            if let Entry::Directory(dir) = entry {
+                if self.descend(depth) {
+                    pending.extend(dir.dirs.iter().rev().map(|handle| (depth + 1, Entry::Directory(fs.dir(*handle)))));
+                    pending.extend(dir.files.iter().rev().map(|handle| (depth + 1, Entry::File(fs.file(*handle)))));
+                }
+            }
+            Some((depth, entry))
+        })
+        .filter(|(depth, entry)| self.matches(entry, *depth))
+        .map(|(_, entry)| entry);
+
+        let limit = self.limit.unwrap_or(usize::MAX);
+        match self.order {
+            None => Box::new(entries.take(limit)),
+            Some(order) => {
+                let mut entries: Vec<Entry> = entries.collect();
+                match order {
+                    SortOrder::Listing => entries.sort_by_key(|entry| entry.generation()),
+                    SortOrder::Name => entries.sort_by(|a, b| a.name().cmp(b.name())),
+                    SortOrder::Size => entries.sort_by(|a, b| b.size().cmp(&a.size()).then(a.name().cmp(b.name()))),
+                }
+                Box::new(entries.into_iter().take(limit))
+            }
+        }
+    }
+}
+
+/// Match `name` against a shell pattern made of `*`, `?` and literal characters
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and of the name character it currently absorbs
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    star = Some((star_p, star_n + 1));
+                    p = star_p + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl BasicFileSystem {
+    /// Query over every entry of the filesystem, see [Query]
+    pub fn query(&self) -> Query<'_> {
+        Query::new(self)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+
+    fn names<'a>(entries: impl Iterator<Item = Entry<'a>>) -> Vec<&'a str> {
+        entries.map(|entry| entry.name()).collect()
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*.txt", "b.txt"));
+        assert!(!glob_match("*.txt", "b.txt.gz"));
+        assert!(glob_match("d.*", "d.log"));
+        assert!(glob_match("?", "k"));
+        assert!(!glob_match("?", "kk"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(!glob_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn query_example() {
+        let state = example_state();
+        let fs = &state.fs;
+
+        assert_eq!(names(fs.query().iter()), ["/", "b.txt", "c.dat", "a", "f", "g", "h.lst", "e", "i", "d", "j",
+            "d.log", "d.ext", "k"]);
+        assert_eq!(fs.query().kind(Kind::Directory).size(..=100_000).iter().map(|entry| entry.size()).sum::<usize>(),
+            95437);
+        assert_eq!(names(fs.query().kind(Kind::File).sort_by(SortOrder::Size).limit(3).iter()),
+            ["b.txt", "c.dat", "d.log"]);
+        assert_eq!(names(fs.query().name("d*").sort_by(SortOrder::Name).iter()), ["d", "d.ext", "d.log"]);
+        assert_eq!(names(fs.query().name("d*").sort_by(SortOrder::Listing).iter()), ["d", "d.log", "d.ext"]);
+        assert_eq!(names(fs.query().depth(2..).kind(Kind::Directory).iter()), ["e"]);
+        assert_eq!(names(fs.query().depth(..1).iter()), ["/"]);
+        assert_eq!(names(fs.query().depth(..=1).kind(Kind::File).iter()), ["b.txt", "c.dat"]);
+
+        let Ok(Entry::Directory(a)) = fs.lookup(fs.root(), "/a") else { panic!("not a directory") };
+        assert_eq!(names(fs.query().under(a.handle).kind(Kind::File).sort_by(SortOrder::Size).iter()),
+            ["h.lst", "f", "g", "i"]);
+        let leaf = |entry: &Entry| matches!(entry, Entry::Directory(dir) if dir.dirs.is_empty());
+        assert_eq!(names(fs.query().filter(leaf).iter()), ["e", "d"]);
+        assert_eq!(names(fs.query().filter(leaf).filter(|entry| entry.size() > 1000).iter()), ["d"]);
+    }
+}
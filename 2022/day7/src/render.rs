@@ -0,0 +1,174 @@
+use std::fmt;
+
+use crate::fs::{BasicFileSystem, DirectoryHandle, Entry};
+
+/// Order of the entries within each directory of a [TreeView]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum SortOrder {
+    /// In the order the entries were first listed, as in the puzzle
+    #[default]
+    Listing,
+    /// Alphabetically, files and directories mixed
+    Name,
+    /// Largest first, ties broken by name
+    Size,
+}
+
+/// Renders a directory in the puzzle's indented tree notation:
+///
+/// ```text
+/// - / (dir)
+///   - a (dir)
+///     - i (file, size=584)
+///   - b.txt (file, size=14848514)
+/// ```
+pub struct TreeView<'a> {
+    fs: &'a BasicFileSystem,
+    root: DirectoryHandle,
+    sizes: bool,
+    order: SortOrder,
+    max_depth: Option<usize>,
+}
+
+impl<'a> TreeView<'a> {
+    pub fn new(root: DirectoryHandle, fs: &'a BasicFileSystem) -> Self {
+        TreeView {
+            fs,
+            root,
+            sizes: false,
+            order: SortOrder::default(),
+            max_depth: None,
+        }
+    }
+
+    /// Annotate directories with their cumulative size
+    pub fn with_sizes(mut self, sizes: bool) -> Self {
+        self.sizes = sizes;
+        self
+    }
+
+    pub fn sort_by(mut self, order: SortOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Do not descend below `max_depth`, the root being at depth 0
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    fn children(&self, dir: DirectoryHandle) -> Vec<Entry<'a>> {
+        let view = self.fs.dir(dir);
+        let mut children: Vec<Entry> = view
+            .dirs
+            .iter()
+            .map(|handle| Entry::Directory(self.fs.dir(*handle)))
+            .chain(view.files.iter().map(|handle| Entry::File(self.fs.file(*handle))))
+            .collect();
+        match self.order {
+            SortOrder::Listing => children.sort_by_key(|entry| entry.generation()),
+            SortOrder::Name => children.sort_by(|a, b| a.name().cmp(b.name())),
+            SortOrder::Size => children.sort_by(|a, b| b.size().cmp(&a.size()).then(a.name().cmp(b.name()))),
+        }
+        children
+    }
+
+    fn fmt_entry(&self, f: &mut fmt::Formatter<'_>, entry: &Entry, depth: usize) -> fmt::Result {
+        let indent = "  ".repeat(depth);
+        match entry {
+            Entry::File(file) => writeln!(f, "{}- {} (file, size={})", indent, file.name, file.size),
+            Entry::Directory(dir) => {
+                if self.sizes {
+                    writeln!(f, "{}- {} (dir, size={})", indent, dir.name, dir.size)?;
+                } else {
+                    writeln!(f, "{}- {} (dir)", indent, dir.name)?;
+                }
+                if self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                    return Ok(());
+                }
+                for child in self.children(dir.handle) {
+                    self.fmt_entry(f, &child, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for TreeView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_entry(f, &Entry::Directory(self.fs.dir(self.root)), 0)
+    }
+}
+
+impl DirectoryHandle {
+    /// Tree view of this directory, see [TreeView]
+    pub fn tree<'a>(&self, fs: &'a BasicFileSystem) -> TreeView<'a> {
+        TreeView::new(*self, fs)
+    }
+}
+
+impl fmt::Display for BasicFileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root().tree(self).fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+
+    /// The default rendering is the tree of the puzzle description
+    #[test]
+    fn render_example() {
+        let state = example_state();
+        assert_eq!(state.fs.to_string(),
+r#"- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - j (file, size=4060174)
+    - d.log (file, size=8033020)
+    - d.ext (file, size=5626152)
+    - k (file, size=7214296)
+"#);
+
+        let d = state.resolve_directory("/d").unwrap();
+        assert_eq!(d.tree(&state.fs).sort_by(SortOrder::Name).to_string(),
+r#"- d (dir)
+  - d.ext (file, size=5626152)
+  - d.log (file, size=8033020)
+  - j (file, size=4060174)
+  - k (file, size=7214296)
+"#);
+    }
+
+    #[test]
+    fn render_options() {
+        let state = example_state();
+        let view = state.fs.root().tree(&state.fs)
+            .with_sizes(true)
+            .sort_by(SortOrder::Size)
+            .max_depth(1);
+        assert_eq!(view.to_string(),
+r#"- / (dir, size=48381165)
+  - d (dir, size=24933642)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - a (dir, size=94853)
+"#);
+
+        let a = state.fs.lookup(state.fs.root(), "/a").unwrap();
+        let Entry::Directory(a) = a else { panic!("not a directory") };
+        assert_eq!(a.handle.tree(&state.fs).max_depth(0).to_string(), "- a (dir)\n");
+    }
+}
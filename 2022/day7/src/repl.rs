@@ -0,0 +1,267 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+
+use crate::fs::{Directory, Entry, File, FileKind, Path, Tree};
+use crate::parse::ShellState;
+
+const HELP: &str = "\
+cd PATH                      change the current directory
+pwd                          print the current directory
+ls [-l] [PATH]               list a directory, -l adds kinds and sizes
+du [-s] [PATH]               cumulative size of every directory, -s for PATH only
+find [PATH] [-type f|d] [-size [+|-]N]
+                             entries below PATH, optionally filtered
+tree [-s] [-L DEPTH] [PATH]  indented tree, -s adds directory sizes
+help                         show this message
+exit                         leave the shell
+";
+
+/// Size filter of `find`, following find(1): `+N` is more than `N` bytes,
+/// `-N` is less than `N` bytes, `N` is exactly `N` bytes
+#[derive(Clone, Copy)]
+enum SizeFilter {
+    Greater(usize),
+    Less(usize),
+    Exactly(usize),
+}
+
+impl SizeFilter {
+    fn parse(arg: &str) -> Result<Self, String> {
+        let number = |digits: &str| digits.parse().map_err(|_| format!("find: invalid size {:?}", arg));
+        if let Some(digits) = arg.strip_prefix('+') {
+            Ok(SizeFilter::Greater(number(digits)?))
+        } else if let Some(digits) = arg.strip_prefix('-') {
+            Ok(SizeFilter::Less(number(digits)?))
+        } else {
+            Ok(SizeFilter::Exactly(number(arg)?))
+        }
+    }
+
+    fn matches(&self, size: usize) -> bool {
+        match *self {
+            SizeFilter::Greater(bound) => size > bound,
+            SizeFilter::Less(bound) => size < bound,
+            SizeFilter::Exactly(bound) => size == bound,
+        }
+    }
+}
+
+/// Interactive shell for exploring a parsed [ShellState]
+pub struct Repl {
+    pub state: ShellState,
+}
+
+impl Repl {
+    pub fn new(state: ShellState) -> Self {
+        Repl { state }
+    }
+
+    /// Run one command line and return what it prints
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            return Ok(String::new());
+        };
+        let args: Vec<&str> = args.collect();
+        match command {
+            "cd" => self.cd(&args),
+            "pwd" => Ok(format!("{}\n", self.state.cwd.abspath(&self.state.fs))),
+            "ls" => self.ls(&args),
+            "du" => self.du(&args),
+            "find" => self.find(&args),
+            "tree" => self.tree(&args),
+            "help" => Ok(HELP.into()),
+            _ => Err(format!("{}: command not found", command)),
+        }
+    }
+
+    /// Read commands from `input` until it ends or `exit` is entered
+    pub fn run(&mut self, input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut lines = input.lines();
+        loop {
+            write!(output, "{}$ ", self.state.cwd.abspath(&self.state.fs))?;
+            output.flush()?;
+            let Some(line) = lines.next() else {
+                return writeln!(output);
+            };
+            let line = line?;
+            if matches!(line.trim(), "exit" | "quit") {
+                return Ok(());
+            }
+            match self.execute(&line) {
+                Ok(text) => write!(output, "{}", text)?,
+                Err(message) => writeln!(output, "{}", message)?,
+            }
+        }
+    }
+
+    fn directory(&self, path: Option<&str>) -> Result<&Directory, String> {
+        let handle = match path {
+            Some(path) => self.state.resolve_directory(path).map_err(|error| error.to_string())?,
+            None => self.state.cwd,
+        };
+        handle.view(&self.state.fs).map_err(|error| error.to_string())
+    }
+
+    fn cd(&mut self, args: &[&str]) -> Result<String, String> {
+        let path = match args {
+            [] => "/",
+            [path] => path,
+            _ => return Err("usage: cd PATH".into()),
+        };
+        self.state.cwd = self.state.resolve_directory(path).map_err(|error| error.to_string())?;
+        Ok(String::new())
+    }
+
+    fn ls(&self, args: &[&str]) -> Result<String, String> {
+        let (long, path) = match args {
+            [] => (false, None),
+            ["-l"] => (true, None),
+            ["-l", path] => (true, Some(*path)),
+            [path] => (false, Some(*path)),
+            _ => return Err("usage: ls [-l] [PATH]".into()),
+        };
+        let fs = &self.state.fs;
+        let view = self.directory(path)?;
+        let mut lines: Vec<(String, String)> = view
+            .dirs
+            .iter()
+            .map(|handle| handle.view(fs).unwrap())
+            .map(|dir| {
+                let line = if long { format!("dir  {:>10} {}/", dir.size, dir.name) } else { format!("{}/", dir.name) };
+                (dir.name.clone(), line)
+            })
+            .chain(view.files.iter().map(|handle| handle.view(fs).unwrap()).map(|file| {
+                let line = match (long, &file.kind) {
+                    (false, _) => file.name.clone(),
+                    (true, FileKind::Regular) => format!("file {:>10} {}", file.size, file.name),
+                    (true, FileKind::Symlink(target)) => format!("link {:>10} {} -> {}", file.size, file.name, target),
+                };
+                (file.name.clone(), line)
+            }))
+            .collect();
+        lines.sort();
+        Ok(lines.into_iter().map(|(_, line)| line + "\n").collect())
+    }
+
+    fn du(&self, args: &[&str]) -> Result<String, String> {
+        let (summarize, path) = match args {
+            [] => (false, None),
+            ["-s"] => (true, None),
+            ["-s", path] => (true, Some(*path)),
+            [path] => (false, Some(*path)),
+            _ => return Err("usage: du [-s] [PATH]".into()),
+        };
+        let fs = &self.state.fs;
+        let view = self.directory(path)?;
+        if summarize {
+            return Ok(format!("{}\t{}\n", view.handle.size(fs), view.handle.abspath(fs)));
+        }
+        // like du(1), directories are reported after their subdirectories
+        Ok(view.handle.fold(fs, String::new(), &|mut accum, entry| {
+            if let Entry::Directory(dir) = entry {
+                accum.push_str(&format!("{}\t{}\n", dir.size, dir.handle.abspath(fs)));
+            }
+            accum
+        }))
+    }
+
+    fn find(&self, args: &[&str]) -> Result<String, String> {
+        let (mut path, mut kind, mut size) = (None, None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-type" => match args.next() {
+                    Some(&"f") => kind = Some(false),
+                    Some(&"d") => kind = Some(true),
+                    _ => return Err("find: -type expects f or d".into()),
+                },
+                "-size" => match args.next() {
+                    Some(arg) => size = Some(SizeFilter::parse(arg)?),
+                    None => return Err("find: -size expects [+|-]N".into()),
+                },
+                arg if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+                arg => return Err(format!("find: unknown argument {:?}", arg)),
+            }
+        }
+        let fs = &self.state.fs;
+        let view = self.directory(path)?;
+        let found = RefCell::new(String::new());
+        let report = |is_dir: bool, entry_size: usize, path: String| {
+            if kind.is_none_or(|kind| kind == is_dir) && size.is_none_or(|size| size.matches(entry_size)) {
+                let mut found = found.borrow_mut();
+                found.push_str(&path);
+                found.push('\n');
+            }
+        };
+        view.handle.walk(
+            fs,
+            &|dir: &Directory| report(true, dir.size, dir.handle.abspath(fs)),
+            &|file: &File| report(false, file.size, file.handle.abspath(fs)),
+        );
+        Ok(found.into_inner())
+    }
+
+    fn tree(&self, args: &[&str]) -> Result<String, String> {
+        let (mut sizes, mut depth, mut path) = (false, None, None);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "-s" => sizes = true,
+                "-L" => {
+                    let arg = args.next().ok_or("tree: -L expects a depth")?;
+                    depth = Some(arg.parse::<usize>().map_err(|_| format!("tree: invalid depth {:?}", arg))?);
+                }
+                arg if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+                arg => return Err(format!("tree: unknown argument {:?}", arg)),
+            }
+        }
+        let view = self.directory(path)?;
+        let tree = view.handle.tree(&self.state.fs).with_sizes(sizes);
+        Ok(match depth {
+            Some(depth) => tree.max_depth(depth),
+            None => tree,
+        }
+        .to_string())
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+
+    #[test]
+    fn repl_commands() {
+        let mut repl = Repl::new(example_state());
+        assert_eq!(repl.execute("pwd"), Ok("/d/\n".into()));
+        assert_eq!(repl.execute("cd ../a/e"), Ok("".into()));
+        assert_eq!(repl.execute("pwd"), Ok("/a/e/\n".into()));
+        assert_eq!(repl.execute("ls /"), Ok("a/\nb.txt\nc.dat\nd/\n".into()));
+        assert_eq!(repl.execute("ls -l .."), Ok(
+            "dir         584 e/\nfile      29116 f\nfile       2557 g\nfile      62596 h.lst\n".into()));
+        assert_eq!(repl.execute("du -s /a"), Ok("94853\t/a/\n".into()));
+        assert_eq!(repl.execute("du /"), Ok("584\t/a/e/\n94853\t/a/\n24933642\t/d/\n48381165\t/\n".into()));
+        assert_eq!(repl.execute("find / -size +8000000"), Ok("/\n/b.txt\n/c.dat\n/d/\n/d/d.log\n".into()));
+        assert_eq!(repl.execute("find / -type d -size -100000"), Ok("/a/\n/a/e/\n".into()));
+        assert_eq!(repl.execute("tree -s -L 1 /"), Ok(
+            "- / (dir, size=48381165)\n  - a (dir, size=94853)\n  - b.txt (file, size=14848514)\n  \
+             - c.dat (file, size=8504156)\n  - d (dir, size=24933642)\n".into()));
+
+        assert_eq!(repl.execute("cd /b.txt"), Err("/b.txt: not a directory".into()));
+        assert_eq!(repl.execute("cd /x"), Err("/x: no such directory".into()));
+        assert_eq!(repl.execute("rm /a"), Err("rm: command not found".into()));
+        assert_eq!(repl.execute("find -size x"), Err("find: invalid size \"x\"".into()));
+    }
+
+    #[test]
+    fn repl_session() {
+        let mut repl = Repl::new(example_state());
+        let mut output = vec![];
+        repl.run("cd /a\nls\nbogus\nexit\npwd\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "/d/$ /a/$ e/\nf\ng\nh.lst\n/a/$ bogus: command not found\n/a/$ ");
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write};
+
+use crate::fs::{BasicFileSystem, DirectoryHandle, Entry, FileHandle, Path};
+
+/// Files whose size has the same number of decimal digits
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Bucket {
+    pub min: usize,
+    /// Inclusive
+    pub max: usize,
+    pub files: usize,
+    pub bytes: usize,
+}
+
+/// Files and bytes sharing an extension or a depth
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct Total {
+    pub files: usize,
+    pub bytes: usize,
+}
+
+/// Where the space goes below a directory.
+///
+/// Hard links are counted once, under the first of their names met by
+/// [DirectoryHandle::fold]. Displays as a table, see [SizeReport::to_csv] for
+/// the same figures as CSV.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct SizeReport {
+    pub total: Total,
+    /// From the smallest sizes up, without the empty buckets at both ends
+    pub histogram: Vec<Bucket>,
+    /// Largest first, ties broken by path
+    pub largest_files: Vec<(String, usize)>,
+    /// Largest first, without the directory the report is about
+    pub largest_dirs: Vec<(String, usize)>,
+    /// By extension, e.g. `.lst`, an empty string for files without one
+    pub extensions: BTreeMap<String, Total>,
+    /// By depth of the files, those of the directory itself being at depth 1
+    pub depths: BTreeMap<usize, Total>,
+}
+
+/// `.ext` of `name`, empty for `name` and `.name`
+fn extension(name: &str) -> &str {
+    match name.rfind('.') {
+        Some(0) | None => "",
+        Some(dot) => &name[dot..],
+    }
+}
+
+/// Index of the decimal bucket of `size`: 0 for empty files, then one bucket
+/// per number of digits
+fn bucket(size: usize) -> usize {
+    if size == 0 { 0 } else { size.ilog10() as usize + 1 }
+}
+
+/// Smallest and largest size of the bucket at `index`, the bucket of the
+/// largest sizes ending at `usize::MAX`
+fn bucket_range(index: usize) -> (usize, usize) {
+    match index {
+        0 => (0, 0),
+        _ => {
+            let max = 10usize.checked_pow(index as u32).map_or(usize::MAX, |bound| bound - 1);
+            (10usize.pow(index as u32 - 1), max)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    total: Total,
+    buckets: BTreeMap<usize, Total>,
+    files: Vec<(usize, FileHandle)>,
+    dirs: Vec<(usize, DirectoryHandle)>,
+    extensions: BTreeMap<String, Total>,
+    depths: BTreeMap<usize, Total>,
+    inodes: HashSet<usize>,
+}
+
+fn add(total: &mut Total, bytes: usize) {
+    total.files += 1;
+    total.bytes += bytes;
+}
+
+/// Largest first, ties broken by path
+fn largest(mut entries: Vec<(usize, String)>, top: usize) -> Vec<(String, usize)> {
+    entries.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    entries.into_iter().take(top).map(|(size, path)| (path, size)).collect()
+}
+
+impl SizeReport {
+    /// Report on `dir`, listing the `top` largest files and directories
+    pub fn new(dir: DirectoryHandle, top: usize, fs: &BasicFileSystem) -> Self {
+        let depth = |handle: DirectoryHandle| {
+            let mut depth = 0;
+            let mut current = handle;
+            while current != dir {
+                current = current.parent(fs);
+                depth += 1;
+            }
+            depth
+        };
+        let tally = dir.fold(fs, Tally::default(), &|mut tally, entry| {
+            match entry {
+                Entry::File(file) => {
+                    if tally.inodes.insert(file.inode) {
+                        add(&mut tally.total, file.size);
+                        add(tally.buckets.entry(bucket(file.size)).or_default(), file.size);
+                        add(tally.extensions.entry(extension(&file.name).into()).or_default(), file.size);
+                        add(tally.depths.entry(depth(file.dir) + 1).or_default(), file.size);
+                        tally.files.push((file.size, file.handle));
+                    }
+                }
+                Entry::Directory(view) if view.handle != dir => tally.dirs.push((view.size, view.handle)),
+                Entry::Directory(_) => (),
+            }
+            tally
+        });
+
+        let histogram = match (tally.buckets.keys().next(), tally.buckets.keys().last()) {
+            (Some(first), Some(last)) => (*first..=*last)
+                .map(|index| {
+                    let (min, max) = bucket_range(index);
+                    let total = tally.buckets.get(&index).copied().unwrap_or_default();
+                    Bucket { min, max, files: total.files, bytes: total.bytes }
+                })
+                .collect(),
+            _ => vec![],
+        };
+        let files = tally.files.into_iter().map(|(size, file)| (size, file.abspath(fs))).collect();
+        let dirs = tally.dirs.into_iter().map(|(size, dir)| (size, dir.abspath(fs))).collect();
+        SizeReport {
+            total: tally.total,
+            histogram,
+            largest_files: largest(files, top),
+            largest_dirs: largest(dirs, top),
+            extensions: tally.extensions,
+            depths: tally.depths,
+        }
+    }
+
+    /// One `section,key,files,bytes` row per figure of the table. Sections
+    /// are `total`, `histogram` (keyed by `min-max`), `file`, `directory`
+    /// (keyed by path, without a file count), `extension` and `depth`.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("section,key,files,bytes\n");
+        let mut row = |section: &str, key: &str, files: Option<usize>, bytes: usize| {
+            let files = files.map(|files| files.to_string()).unwrap_or_default();
+            writeln!(csv, "{},{},{},{}", section, csv_field(key), files, bytes).unwrap();
+        };
+        row("total", "", Some(self.total.files), self.total.bytes);
+        for bucket in &self.histogram {
+            row("histogram", &format!("{}-{}", bucket.min, bucket.max), Some(bucket.files), bucket.bytes);
+        }
+        for (path, size) in &self.largest_files {
+            row("file", path, Some(1), *size);
+        }
+        for (path, size) in &self.largest_dirs {
+            row("directory", path, None, *size);
+        }
+        for (extension, total) in &self.extensions {
+            row("extension", extension, Some(total.files), total.bytes);
+        }
+        for (depth, total) in &self.depths {
+            row("depth", &depth.to_string(), Some(total.files), total.bytes);
+        }
+        csv
+    }
+}
+
+/// Quote `field` if it holds a separator, a quote or a line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.into()
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} files, {} bytes", self.total.files, self.total.bytes)?;
+        writeln!(f, "\n{:>12} {:>12} {:>8} {:>12}", "min", "max", "files", "bytes")?;
+        for bucket in &self.histogram {
+            writeln!(f, "{:>12} {:>12} {:>8} {:>12}", bucket.min, bucket.max, bucket.files, bucket.bytes)?;
+        }
+        writeln!(f, "\n{:>12}  largest files", "bytes")?;
+        for (path, size) in &self.largest_files {
+            writeln!(f, "{:>12}  {}", size, path)?;
+        }
+        writeln!(f, "\n{:>12}  largest directories", "bytes")?;
+        for (path, size) in &self.largest_dirs {
+            writeln!(f, "{:>12}  {}", size, path)?;
+        }
+        writeln!(f, "\n{:<12} {:>8} {:>12}", "extension", "files", "bytes")?;
+        for (extension, total) in &self.extensions {
+            let extension = if extension.is_empty() { "(none)" } else { extension };
+            writeln!(f, "{:<12} {:>8} {:>12}", extension, total.files, total.bytes)?;
+        }
+        writeln!(f, "\n{:<12} {:>8} {:>12}", "depth", "files", "bytes")?;
+        for (depth, total) in &self.depths {
+            writeln!(f, "{:<12} {:>8} {:>12}", depth, total.files, total.bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl DirectoryHandle {
+    /// See [SizeReport::new]
+    pub fn size_report(&self, top: usize, fs: &BasicFileSystem) -> SizeReport {
+        SizeReport::new(*self, top, fs)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::{example_state, EXAMPLE};
+    use crate::parse::parse;
+
+    #[test]
+    fn report_example() {
+        let state = example_state();
+        let report = state.fs.root().size_report(3, &state.fs);
+
+        assert_eq!(report.to_string(),
+r#"10 files, 48381165 bytes
+
+         min          max    files        bytes
+         100          999        1          584
+        1000         9999        1         2557
+       10000        99999        2        91712
+      100000       999999        0            0
+     1000000      9999999        5     33437798
+    10000000     99999999        1     14848514
+
+       bytes  largest files
+    14848514  /b.txt
+     8504156  /c.dat
+     8033020  /d/d.log
+
+       bytes  largest directories
+    24933642  /d/
+       94853  /a/
+         584  /a/e/
+
+extension       files        bytes
+(none)              5     11306727
+.dat                1      8504156
+.ext                1      5626152
+.log                1      8033020
+.lst                1        62596
+.txt                1     14848514
+
+depth           files        bytes
+1                   2     23352670
+2                   7     25027911
+3                   1          584
+"#);
+        assert_eq!(report.to_csv().lines().take(4).collect::<Vec<_>>(), [
+            "section,key,files,bytes",
+            "total,,10,48381165",
+            "histogram,100-999,1,584",
+            "histogram,1000-9999,1,2557",
+        ]);
+        assert!(report.to_csv().contains("\ndirectory,/a/e/,,584\nextension,,5,11306727\n"));
+        assert_eq!(report.histogram.iter().map(|bucket| bucket.bytes).sum::<usize>(), report.total.bytes);
+
+        // a subtree, hard links counted once and awkward names quoted
+        let mut state = parse(format!("{}$ cd /a\n$ touch 0 x,\"y\".lst\n", EXAMPLE)).unwrap();
+        let mut a = state.resolve_directory("/a").unwrap();
+        let f = state.fs.lookup(a, "f").map(|entry| match entry {
+            Entry::File(file) => file.handle,
+            Entry::Directory(_) => unreachable!(),
+        }).unwrap();
+        a.new_hard_link("f2".into(), f, &mut state.fs).unwrap();
+        let report = a.size_report(1, &state.fs);
+        assert_eq!(report.total, Total { files: 5, bytes: 94853 });
+        assert_eq!(report.histogram[0], Bucket { min: 0, max: 0, files: 1, bytes: 0 });
+        assert_eq!(report.largest_dirs, [("/a/e/".to_string(), 584)]);
+        assert_eq!(report.depths.keys().copied().collect::<Vec<_>>(), [1, 2]);
+        assert!(report.to_csv().contains("\nextension,.lst,2,62596\n"));
+        let report = state.fs.root().size_report(20, &state.fs);
+        assert!(report.to_csv().contains("\nfile,\"/a/x,\"\"y\"\".lst\",1,0\n"));
+
+        // the largest sizes have a bucket too
+        let mut fs = BasicFileSystem::new();
+        fs.root().new_file("huge".into(), usize::MAX, &mut fs).unwrap();
+        let report = fs.root().size_report(1, &fs);
+        assert_eq!(report.histogram, [Bucket { min: 10usize.pow(19), max: usize::MAX, files: 1, bytes: usize::MAX }]);
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+
+use crate::fs::{BasicFileSystem, Directory, DirectoryHandle, Entry};
+
+/// Entries of a directory and its subtree, each directory before its files
+/// and then its subdirectories, see [DirectoryHandle::pre_order]
+pub struct PreOrder<'a> {
+    fs: &'a BasicFileSystem,
+    pending: Vec<(usize, Entry<'a>)>,
+}
+
+impl<'a> Iterator for PreOrder<'a> {
+    type Item = (usize, Entry<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, entry) = self.pending.pop()?;
+        if let Entry::Directory(dir) = entry {
+            let fs = self.fs;
+            self.pending.extend(dir.dirs.iter().rev().map(|handle| (depth + 1, Entry::Directory(fs.dir(*handle)))));
+            self.pending.extend(dir.files.iter().rev().map(|handle| (depth + 1, Entry::File(fs.file(*handle)))));
+        }
+        Some((depth, entry))
+    }
+}
+
+/// Entries of a directory and its subtree, each directory after its
+/// subdirectories and then its files, see [DirectoryHandle::post_order]
+pub struct PostOrder<'a> {
+    fs: &'a BasicFileSystem,
+    /// Directories being visited, with the number of their subdirectories and
+    /// files already yielded
+    pending: Vec<(&'a Directory, usize, usize)>,
+}
+
+impl<'a> Iterator for PostOrder<'a> {
+    type Item = (usize, Entry<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let depth = self.pending.len().checked_sub(1)?;
+            let (dir, dirs_done, files_done) = self.pending.last_mut().unwrap();
+            let dir: &'a Directory = dir;
+            if let Some(handle) = dir.dirs.get(*dirs_done) {
+                *dirs_done += 1;
+                self.pending.push((self.fs.dir(*handle), 0, 0));
+            } else if let Some(handle) = dir.files.get(*files_done) {
+                *files_done += 1;
+                return Some((depth + 1, Entry::File(self.fs.file(*handle))));
+            } else {
+                self.pending.pop();
+                return Some((depth, Entry::Directory(dir)));
+            }
+        }
+    }
+}
+
+/// Entries of a directory and its subtree, level by level, see
+/// [DirectoryHandle::breadth_first]
+pub struct BreadthFirst<'a> {
+    fs: &'a BasicFileSystem,
+    pending: VecDeque<(usize, Entry<'a>)>,
+}
+
+impl<'a> Iterator for BreadthFirst<'a> {
+    type Item = (usize, Entry<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (depth, entry) = self.pending.pop_front()?;
+        if let Entry::Directory(dir) = entry {
+            let fs = self.fs;
+            self.pending.extend(dir.files.iter().map(|handle| (depth + 1, Entry::File(fs.file(*handle)))));
+            self.pending.extend(dir.dirs.iter().map(|handle| (depth + 1, Entry::Directory(fs.dir(*handle)))));
+        }
+        Some((depth, entry))
+    }
+}
+
+/// Traversals yield `(depth, entry)` pairs, this directory being at depth 0.
+/// They keep their own stack or queue, so they handle directory chains of any
+/// depth and can be stopped early.
+impl DirectoryHandle {
+    /// Same order as [DirectoryHandle::walk]
+    pub fn pre_order<'a>(&self, fs: &'a BasicFileSystem) -> PreOrder<'a> {
+        PreOrder { fs, pending: vec![(0, Entry::Directory(fs.dir(*self)))] }
+    }
+
+    /// Same order as [DirectoryHandle::fold]
+    pub fn post_order<'a>(&self, fs: &'a BasicFileSystem) -> PostOrder<'a> {
+        PostOrder { fs, pending: vec![(fs.dir(*self), 0, 0)] }
+    }
+
+    pub fn breadth_first<'a>(&self, fs: &'a BasicFileSystem) -> BreadthFirst<'a> {
+        BreadthFirst { fs, pending: VecDeque::from([(0, Entry::Directory(fs.dir(*self)))]) }
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::fixtures::example_state;
+    use crate::fs::{Path, Tree};
+
+    fn names<'a>(entries: impl Iterator<Item = (usize, Entry<'a>)>) -> Vec<(usize, &'a str)> {
+        entries.map(|(depth, entry)| (depth, entry.name())).collect()
+    }
+
+    #[test]
+    fn traversal_order() {
+        let state = example_state();
+        let (fs, root) = (&state.fs, state.fs.root());
+
+        assert_eq!(names(root.pre_order(fs)), [(0, "/"), (1, "b.txt"), (1, "c.dat"), (1, "a"), (2, "f"),
+            (2, "g"), (2, "h.lst"), (2, "e"), (3, "i"), (1, "d"), (2, "j"), (2, "d.log"), (2, "d.ext"), (2, "k")]);
+        assert_eq!(names(root.post_order(fs)), [(3, "i"), (2, "e"), (2, "f"), (2, "g"), (2, "h.lst"), (1, "a"),
+            (2, "j"), (2, "d.log"), (2, "d.ext"), (2, "k"), (1, "d"), (1, "b.txt"), (1, "c.dat"), (0, "/")]);
+        assert_eq!(names(root.breadth_first(fs)), [(0, "/"), (1, "b.txt"), (1, "c.dat"), (1, "a"), (1, "d"),
+            (2, "f"), (2, "g"), (2, "h.lst"), (2, "e"), (2, "j"), (2, "d.log"), (2, "d.ext"), (2, "k"), (3, "i")]);
+
+        // same order as the callback traversals
+        let folded = root.fold(fs, vec![], &|mut names, entry| {
+            names.push(entry.name().to_string());
+            names
+        });
+        assert_eq!(root.post_order(fs).map(|(_, entry)| entry.name()).collect::<Vec<_>>(), folded);
+
+        // iterators short-circuit
+        let first_large = root
+            .breadth_first(fs)
+            .find(|(_, entry)| matches!(entry, Entry::File(_)) && entry.size() > 8_100_000);
+        assert_eq!(first_large.map(|(_, entry)| entry.name()), Some("b.txt"));
+        let shallow = root.pre_order(fs).take_while(|(depth, _)| *depth < 2).count();
+        assert_eq!(shallow, 4);
+    }
+
+    /// Chains far deeper than the call stack allows for recursion
+    #[test]
+    fn deep_chain() {
+        let mut fs = BasicFileSystem::new();
+        fs.check_mutations(false);
+        let mut dir = fs.root();
+        for _ in 0..200_000 {
+            dir = dir.new_directory("d".into(), &mut fs).unwrap();
+        }
+        let _ = dir.new_file("f".into(), 7, &mut fs).unwrap();
+
+        assert_eq!(fs.root().pre_order(&fs).count(), 200_002);
+        assert_eq!(fs.root().post_order(&fs).last().map(|(depth, entry)| (depth, entry.size())), Some((0, 7)));
+        assert_eq!(fs.root().breadth_first(&fs).map(|(depth, _)| depth).max(), Some(200_001));
+        assert_eq!(fs.root().fold(&fs, 0, &|count, _| count + 1), 200_002);
+        assert_eq!(dir.abspath(&fs).len(), 2 * 200_000 + 1);
+        assert_eq!(dir.size(&fs), 7);
+    }
+}